@@ -8,8 +8,8 @@ use serde_json::json;
 use tracing::info;
 use tracing_subscriber::EnvFilter;
 use va_skills::{
-    CommandRequest, CommandResponse, DateNowResult, ExecuteRequest, TimeNowResult,
-    COMMAND_DATE_NOW, COMMAND_TIME_NOW,
+    no_parameters, CommandDescriptor, CommandRequest, CommandResponse, CommandsResponse,
+    DateNowResult, ExecuteRequest, TimeNowResult, COMMAND_DATE_NOW, COMMAND_TIME_NOW,
 };
 
 use crate::config::Config;
@@ -19,20 +19,6 @@ struct HealthResponse {
     status: &'static str,
 }
 
-#[derive(Serialize)]
-struct CommandDescriptor {
-    command: &'static str,
-    description: &'static str,
-    example_request: serde_json::Value,
-    example_response: serde_json::Value,
-}
-
-#[derive(Serialize)]
-struct CommandsResponse {
-    instructions: &'static str,
-    commands: Vec<CommandDescriptor>,
-}
-
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenvy::dotenv().ok();
@@ -68,8 +54,9 @@ async fn health() -> impl Responder {
 async fn commands() -> impl Responder {
     let now = Local::now();
     let time_now = CommandDescriptor {
-        command: COMMAND_TIME_NOW,
-        description: "Return the server's current local time.",
+        command: COMMAND_TIME_NOW.to_string(),
+        description: "Return the server's current local time.".to_string(),
+        parameters: no_parameters(),
         example_request: json!({ "command": COMMAND_TIME_NOW }),
         example_response: json!({
             "command": COMMAND_TIME_NOW,
@@ -81,8 +68,9 @@ async fn commands() -> impl Responder {
     };
 
     let date_now = CommandDescriptor {
-        command: COMMAND_DATE_NOW,
-        description: "Return the server's current local date.",
+        command: COMMAND_DATE_NOW.to_string(),
+        description: "Return the server's current local date.".to_string(),
+        parameters: no_parameters(),
         example_request: json!({ "command": COMMAND_DATE_NOW }),
         example_response: json!({
             "command": COMMAND_DATE_NOW,
@@ -94,7 +82,10 @@ async fn commands() -> impl Responder {
     };
 
     let response = CommandsResponse {
-        instructions: "POST /execute with a JSON body containing {\"command\": \"...\"}. Each command returns a result object shaped for that command.",
+        instructions:
+            "POST /execute with a JSON body containing {\"command\": \"...\"}. Each command \
+             returns a result object shaped for that command."
+                .to_string(),
         commands: vec![time_now, date_now],
     };
 
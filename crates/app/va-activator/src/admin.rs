@@ -0,0 +1,200 @@
+use std::collections::HashSet;
+
+use actix_web::http::{header, StatusCode};
+use actix_web::{delete, get, put, web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::auth::constant_time_eq;
+use crate::config::normalize_word;
+use crate::reload::UpdateError;
+use crate::rules::{sorted, Rules};
+use crate::AppState;
+
+/// The word lists that can be managed through `/admin/{list}`.
+#[derive(Clone, Copy)]
+enum WordList {
+    Activation,
+    Stop,
+}
+
+impl WordList {
+    fn from_path(name: &str) -> Option<Self> {
+        match name {
+            "activation-words" => Some(Self::Activation),
+            "stop-words" => Some(Self::Stop),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Activation => "activation words",
+            Self::Stop => "stop words",
+        }
+    }
+
+    fn words(self, rules: &Rules) -> &HashSet<String> {
+        match self {
+            Self::Activation => &rules.activation_words,
+            Self::Stop => &rules.stop_words,
+        }
+    }
+
+    fn words_mut(self, rules: &mut Rules) -> &mut HashSet<String> {
+        match self {
+            Self::Activation => &mut rules.activation_words,
+            Self::Stop => &mut rules.stop_words,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct WordsRequest {
+    words: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct WordsResponse<'a> {
+    words: Vec<&'a str>,
+}
+
+#[derive(Serialize)]
+struct AdminError {
+    status: &'static str,
+    message: String,
+}
+
+#[get("/admin/{list}")]
+pub(crate) async fn get_words(
+    state: web::Data<AppState>,
+    request: HttpRequest,
+    list: web::Path<String>,
+) -> HttpResponse {
+    let list = match authorize(&state, &request).and_then(|()| word_list(&list)) {
+        Ok(list) => list,
+        Err(response) => return response,
+    };
+    let rules = state.rules.current();
+    HttpResponse::Ok().json(WordsResponse {
+        words: sorted(list.words(&rules)),
+    })
+}
+
+/// Replaces the list with the given words.
+#[put("/admin/{list}")]
+pub(crate) async fn replace_words(
+    state: web::Data<AppState>,
+    request: HttpRequest,
+    list: web::Path<String>,
+    payload: web::Json<WordsRequest>,
+) -> HttpResponse {
+    let list = match authorize(&state, &request).and_then(|()| word_list(&list)) {
+        Ok(list) => list,
+        Err(response) => return response,
+    };
+    update(&state, list, &payload.words, |current, words| {
+        *current = words
+    })
+}
+
+/// Removes the given words from the list. Words that are not in the list are ignored.
+#[delete("/admin/{list}")]
+pub(crate) async fn remove_words(
+    state: web::Data<AppState>,
+    request: HttpRequest,
+    list: web::Path<String>,
+    payload: web::Json<WordsRequest>,
+) -> HttpResponse {
+    let list = match authorize(&state, &request).and_then(|()| word_list(&list)) {
+        Ok(list) => list,
+        Err(response) => return response,
+    };
+    update(&state, list, &payload.words, |current, words| {
+        current.retain(|word| !words.contains(word));
+    })
+}
+
+fn update(
+    state: &AppState,
+    list: WordList,
+    words: &[String],
+    change: impl FnOnce(&mut HashSet<String>, HashSet<String>),
+) -> HttpResponse {
+    let words = match normalize_words(words) {
+        Ok(words) => words,
+        Err(message) => return error(StatusCode::BAD_REQUEST, message),
+    };
+
+    let result = state.rules.update(state.config.admin_persist, |rules| {
+        change(list.words_mut(rules), words);
+        rules.check_word_lists()
+    });
+    match result {
+        Ok(rules) => {
+            info!("{} updated through the admin API", list.name());
+            HttpResponse::Ok().json(WordsResponse {
+                words: sorted(list.words(&rules)),
+            })
+        }
+        Err(err @ UpdateError::Invalid(_)) => error(StatusCode::BAD_REQUEST, err.to_string()),
+        Err(err @ UpdateError::Persist(_)) => {
+            warn!("admin update of {} failed: {err}", list.name());
+            error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+        }
+    }
+}
+
+fn normalize_words(words: &[String]) -> Result<HashSet<String>, String> {
+    if words.is_empty() {
+        return Err("words must contain at least one word".to_string());
+    }
+    words
+        .iter()
+        .map(|word| {
+            let word = normalize_word(word);
+            if word.is_empty() {
+                Err("words must not be empty".to_string())
+            } else {
+                Ok(word)
+            }
+        })
+        .collect()
+}
+
+/// Checks the `ADMIN_TOKEN` bearer token. Every endpoint that exposes the rules or what users say
+/// goes through this check.
+pub(crate) fn authorize(state: &AppState, request: &HttpRequest) -> Result<(), HttpResponse> {
+    let Some(token) = &state.config.admin_token else {
+        return Err(error(
+            StatusCode::NOT_FOUND,
+            "admin API is disabled".to_string(),
+        ));
+    };
+    let provided = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if !provided.is_some_and(|provided| constant_time_eq(provided.as_bytes(), token.as_bytes())) {
+        return Err(error(
+            StatusCode::UNAUTHORIZED,
+            "missing or invalid admin token".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Resolves the list named in the path.
+fn word_list(list: &str) -> Result<WordList, HttpResponse> {
+    WordList::from_path(list)
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, format!("unknown word list: {list}")))
+}
+
+fn error(status: StatusCode, message: String) -> HttpResponse {
+    HttpResponse::build(status).json(AdminError {
+        status: "error",
+        message,
+    })
+}
@@ -0,0 +1,108 @@
+use actix_web::http::header::HeaderMap;
+use ring::hmac;
+
+use crate::config::WebhookAuthMode;
+
+pub(crate) const SECRET_HEADER: &str = "x-webhook-secret";
+pub(crate) const SIGNATURE_HEADER: &str = "x-signature";
+const SIGNATURE_PREFIX: &str = "sha256=";
+
+/// How callers of `/webhook` prove they are allowed to send utterances.
+pub(crate) enum WebhookAuth {
+    None,
+    /// The shared secret is sent as is in `X-Webhook-Secret`.
+    Secret(String),
+    /// `X-Signature: sha256=<hex>` carries an HMAC-SHA256 of the raw body keyed with the shared
+    /// secret.
+    Hmac(hmac::Key),
+}
+
+impl WebhookAuth {
+    /// `secret` is ignored for [`WebhookAuthMode::None`].
+    pub(crate) fn new(mode: WebhookAuthMode, secret: &str) -> Self {
+        match mode {
+            WebhookAuthMode::None => Self::None,
+            WebhookAuthMode::Secret => Self::Secret(secret.to_string()),
+            WebhookAuthMode::Hmac => {
+                Self::Hmac(hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()))
+            }
+        }
+    }
+
+    pub(crate) fn verify(&self, headers: &HeaderMap, body: &[u8]) -> bool {
+        match self {
+            Self::None => true,
+            Self::Secret(secret) => headers
+                .get(SECRET_HEADER)
+                .is_some_and(|value| constant_time_eq(value.as_bytes(), secret.as_bytes())),
+            Self::Hmac(key) => headers
+                .get(SIGNATURE_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix(SIGNATURE_PREFIX))
+                .and_then(decode_hex)
+                .is_some_and(|signature| hmac::verify(key, body, &signature).is_ok()),
+        }
+    }
+}
+
+/// Compares without exiting early, so response times do not reveal how much of a secret matched.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    fn headers(name: &'static str, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static(name),
+            HeaderValue::from_str(value).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn verifies_shared_secret() {
+        let auth = WebhookAuth::new(WebhookAuthMode::Secret, "s3cret");
+
+        assert!(auth.verify(&headers(SECRET_HEADER, "s3cret"), b"{}"));
+        assert!(!auth.verify(&headers(SECRET_HEADER, "s3cre"), b"{}"));
+        assert!(!auth.verify(&HeaderMap::new(), b"{}"));
+    }
+
+    #[test]
+    fn verifies_hmac_signature() {
+        let auth = WebhookAuth::new(WebhookAuthMode::Hmac, "s3cret");
+        let body = br#"{"text":"va play music"}"#;
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"s3cret");
+        let signature = hmac::sign(&key, body)
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+
+        assert!(auth.verify(
+            &headers(SIGNATURE_HEADER, &format!("sha256={signature}")),
+            body
+        ));
+        assert!(!auth.verify(
+            &headers(SIGNATURE_HEADER, &format!("sha256={signature}")),
+            b"{}"
+        ));
+        assert!(!auth.verify(&headers(SIGNATURE_HEADER, &signature), body));
+        assert!(!auth.verify(&headers(SIGNATURE_HEADER, "sha256=zz"), body));
+    }
+}
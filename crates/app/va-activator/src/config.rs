@@ -1,59 +1,380 @@
 use std::collections::HashSet;
 use std::env;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 
 use crate::error::Error;
+use crate::matcher::MatchOptions;
+use crate::rules::{RuleDefaults, Rules};
 
 const ENV_ACTIVATION_WORDS: &str = "ACTIVATION_WORDS";
 const ENV_STOP_WORDS: &str = "STOP_WORDS";
+const ENV_STOP_WORD_POLICY: &str = "STOP_WORD_POLICY";
 const ENV_BIND_ADDR: &str = "BIND_ADDR";
 const ENV_WEBHOOK_URL: &str = "WEBHOOK_URL";
+const ENV_RULES_FILE: &str = "RULES_FILE";
+const ENV_RULES_WATCH_INTERVAL_MS: &str = "RULES_WATCH_INTERVAL_MS";
+const ENV_FORWARD_CONNECT_TIMEOUT_MS: &str = "FORWARD_CONNECT_TIMEOUT_MS";
+const ENV_FORWARD_TIMEOUT_MS: &str = "FORWARD_TIMEOUT_MS";
+const ENV_FORWARD_RETRIES: &str = "FORWARD_RETRIES";
+const ENV_FORWARD_RETRY_BACKOFF_MS: &str = "FORWARD_RETRY_BACKOFF_MS";
+const ENV_BREAKER_FAILURE_THRESHOLD: &str = "BREAKER_FAILURE_THRESHOLD";
+const ENV_BREAKER_COOLDOWN_MS: &str = "BREAKER_COOLDOWN_MS";
+const ENV_FORWARD_MODE: &str = "FORWARD_MODE";
+const ENV_QUEUE_CAPACITY: &str = "QUEUE_CAPACITY";
+const ENV_DEDUP_TTL_MS: &str = "DEDUP_TTL_MS";
+const ENV_LISTEN_WINDOW_MS: &str = "LISTEN_WINDOW_MS";
+const ENV_MIN_ACTIVATION_CONFIDENCE: &str = "MIN_ACTIVATION_CONFIDENCE";
+const ENV_WEBHOOK_AUTH: &str = "WEBHOOK_AUTH";
+const ENV_WEBHOOK_SECRET: &str = "WEBHOOK_SECRET";
+const ENV_RATE_LIMIT_RPS: &str = "RATE_LIMIT_RPS";
+const ENV_RATE_LIMIT_BURST: &str = "RATE_LIMIT_BURST";
+const ENV_RATE_LIMIT_KEY: &str = "RATE_LIMIT_KEY";
+const ENV_ADMIN_TOKEN: &str = "ADMIN_TOKEN";
+const ENV_ADMIN_PERSIST: &str = "ADMIN_PERSIST";
+
+/// Default route for `va-activator eval`, which never forwards anything.
+const EVAL_WEBHOOK_URL: &str = "http://127.0.0.1:9/webhook";
+
+/// Decides when a command is treated as a stop request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum StopWordPolicy {
+    /// The command consists solely of stop words, e.g. `va stop`.
+    Only,
+    /// The command starts with a stop word, e.g. `va cancel the alarm`.
+    Start,
+    /// A stop word appears anywhere in the command.
+    Anywhere,
+}
+
+impl StopWordPolicy {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "only" => Some(Self::Only),
+            "start" => Some(Self::Start),
+            "anywhere" => Some(Self::Anywhere),
+            _ => None,
+        }
+    }
+}
+
+/// How `/webhook` delivers accepted commands downstream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ForwardMode {
+    /// Respond after the downstream has accepted the command.
+    Sync,
+    /// Respond right away and deliver through the in-process queue.
+    Async,
+}
+
+impl ForwardMode {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "sync" => Some(Self::Sync),
+            "async" => Some(Self::Async),
+            _ => None,
+        }
+    }
+}
+
+/// How callers of `/webhook` authenticate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum WebhookAuthMode {
+    None,
+    /// `X-Webhook-Secret` must equal `WEBHOOK_SECRET`.
+    Secret,
+    /// `X-Signature` must be an HMAC-SHA256 of the body keyed with `WEBHOOK_SECRET`.
+    Hmac,
+}
+
+impl WebhookAuthMode {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "none" => Some(Self::None),
+            "secret" => Some(Self::Secret),
+            "hmac" => Some(Self::Hmac),
+            _ => None,
+        }
+    }
+}
+
+/// What `/webhook` requests are rate limited by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RateLimitKey {
+    /// The peer address of the connection.
+    Ip,
+    /// The `source` field of the request.
+    Source,
+}
+
+impl RateLimitKey {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "ip" => Some(Self::Ip),
+            "source" => Some(Self::Source),
+            _ => None,
+        }
+    }
+}
+
+/// Timeouts, retries and circuit breaker settings for downstream requests.
+#[derive(Clone, Debug)]
+pub(crate) struct ForwardSettings {
+    pub(crate) connect_timeout: Duration,
+    /// Default request timeout; a route's `timeout_ms` takes precedence.
+    pub(crate) request_timeout: Duration,
+    pub(crate) max_retries: u32,
+    /// Delay before the first retry, doubled for every further attempt.
+    pub(crate) retry_backoff: Duration,
+    /// Consecutive failures that open a route's breaker; `0` disables the breaker.
+    pub(crate) breaker_threshold: u32,
+    pub(crate) breaker_cooldown: Duration,
+}
+
+impl ForwardSettings {
+    fn from_env() -> Result<Self, Error> {
+        Ok(Self {
+            connect_timeout: Duration::from_millis(parse_env(
+                ENV_FORWARD_CONNECT_TIMEOUT_MS,
+                2_000,
+            )?),
+            request_timeout: Duration::from_millis(parse_env(ENV_FORWARD_TIMEOUT_MS, 30_000)?),
+            max_retries: parse_env(ENV_FORWARD_RETRIES, 2)?,
+            retry_backoff: Duration::from_millis(parse_env(ENV_FORWARD_RETRY_BACKOFF_MS, 200)?),
+            breaker_threshold: parse_env(ENV_BREAKER_FAILURE_THRESHOLD, 5)?,
+            breaker_cooldown: Duration::from_millis(parse_env(ENV_BREAKER_COOLDOWN_MS, 30_000)?),
+        })
+    }
+}
 
 #[derive(Clone)]
 pub(crate) struct Config {
-    pub(crate) activation_words: HashSet<String>,
-    pub(crate) stop_words: HashSet<String>,
+    pub(crate) stop_word_policy: StopWordPolicy,
     pub(crate) bind_addr: String,
-    pub(crate) webhook_url: String,
+    pub(crate) rules_file: Option<PathBuf>,
+    /// Polling interval for changes to the rules file; zero disables polling.
+    pub(crate) rules_watch_interval: Duration,
+    pub(crate) rule_defaults: RuleDefaults,
+    /// Rules loaded at startup. The live rules are kept in [`crate::reload::RuleStore`].
+    pub(crate) rules: Rules,
+    pub(crate) forward: ForwardSettings,
+    pub(crate) forward_mode: ForwardMode,
+    pub(crate) queue_capacity: usize,
+    /// Window in which a repeated command from the same source is suppressed; zero disables
+    /// suppression.
+    pub(crate) dedup_ttl: Duration,
+    /// How long a source keeps listening after a bare activation word; zero disables listening
+    /// windows.
+    pub(crate) listen_window: Duration,
+    /// Lowest recognizer confidence at which an activation word is accepted; zero accepts any
+    /// confidence.
+    pub(crate) min_activation_confidence: f32,
+    pub(crate) webhook_auth: WebhookAuthMode,
+    pub(crate) webhook_secret: Option<String>,
+    /// Requests per second allowed per rate limit key; zero disables rate limiting.
+    pub(crate) rate_limit_rps: f64,
+    /// Requests a key may send at once before the rate applies.
+    pub(crate) rate_limit_burst: u32,
+    pub(crate) rate_limit_key: RateLimitKey,
+    /// Bearer token for the admin API; the admin API is disabled when unset.
+    pub(crate) admin_token: Option<String>,
+    /// Whether word list changes made through the admin API are written back to the rules file.
+    pub(crate) admin_persist: bool,
 }
 
 impl Config {
     pub(crate) fn from_env() -> Result<Self, Error> {
-        let activation_words_raw = env::var(ENV_ACTIVATION_WORDS)
-            .map_err(|_| format!("{ENV_ACTIVATION_WORDS} is not set"))?;
-        let activation_words = activation_words_raw
-            .split(',')
-            .map(|word| word.trim().to_lowercase())
-            .filter(|word| !word.is_empty())
-            .collect::<HashSet<_>>();
-        if activation_words.is_empty() {
-            return Err(format!("{ENV_ACTIVATION_WORDS} must contain at least one word").into());
-        }
-
-        let stop_words_raw =
-            env::var(ENV_STOP_WORDS).map_err(|_| format!("{ENV_STOP_WORDS} is not set"))?;
-        let stop_words = stop_words_raw
-            .split(',')
-            .map(|word| word.trim().to_lowercase())
-            .filter(|word| !word.is_empty())
-            .collect::<HashSet<_>>();
-        if stop_words.is_empty() {
-            return Err(format!("{ENV_STOP_WORDS} must contain at least one word").into());
-        }
+        let MatchOptions {
+            stop_word_policy,
+            min_activation_confidence,
+        } = match_options_from_env()?;
 
         let bind_addr = env::var(ENV_BIND_ADDR).unwrap_or_else(|_| "127.0.0.1:8090".to_string());
-        let webhook_url = env::var(ENV_WEBHOOK_URL)
-            .map_err(|_| format!("{ENV_WEBHOOK_URL} is not set"))?
-            .trim()
-            .to_string();
-        if webhook_url.is_empty() {
-            return Err(format!("{ENV_WEBHOOK_URL} must not be empty").into());
+        let webhook_url = match env::var(ENV_WEBHOOK_URL) {
+            Ok(value) if value.trim().is_empty() => {
+                return Err(format!("{ENV_WEBHOOK_URL} must not be empty").into());
+            }
+            Ok(value) => Some(value.trim().to_string()),
+            Err(_) => None,
+        };
+
+        let rule_defaults = RuleDefaults {
+            webhook_url,
+            activation_words: words_from_env(ENV_ACTIVATION_WORDS)?,
+            stop_words: words_from_env(ENV_STOP_WORDS)?,
+        };
+        let rules_file = env::var(ENV_RULES_FILE).ok().map(PathBuf::from);
+        let rules = match &rules_file {
+            Some(path) => Rules::load(path, &rule_defaults)?,
+            None => {
+                if rule_defaults.webhook_url.is_none() {
+                    return Err(format!("{ENV_WEBHOOK_URL} is not set").into());
+                }
+                if rule_defaults.activation_words.is_none() {
+                    return Err(format!("{ENV_ACTIVATION_WORDS} is not set").into());
+                }
+                if rule_defaults.stop_words.is_none() {
+                    return Err(format!("{ENV_STOP_WORDS} is not set").into());
+                }
+                Rules::from_defaults(&rule_defaults)?
+            }
+        };
+
+        let forward_mode = match env::var(ENV_FORWARD_MODE) {
+            Ok(value) => ForwardMode::parse(&value)
+                .ok_or_else(|| format!("{ENV_FORWARD_MODE} must be one of: sync, async"))?,
+            Err(_) => ForwardMode::Sync,
+        };
+
+        let webhook_auth = match env::var(ENV_WEBHOOK_AUTH) {
+            Ok(value) => WebhookAuthMode::parse(&value)
+                .ok_or_else(|| format!("{ENV_WEBHOOK_AUTH} must be one of: none, secret, hmac"))?,
+            Err(_) => WebhookAuthMode::None,
+        };
+        let webhook_secret = env::var(ENV_WEBHOOK_SECRET)
+            .ok()
+            .filter(|value| !value.trim().is_empty());
+        if webhook_auth != WebhookAuthMode::None && webhook_secret.is_none() {
+            return Err(
+                format!("{ENV_WEBHOOK_SECRET} is required when {ENV_WEBHOOK_AUTH} is set").into(),
+            );
+        }
+
+        let rate_limit_rps: f64 = parse_env(ENV_RATE_LIMIT_RPS, 0.0)?;
+        if !rate_limit_rps.is_finite() || rate_limit_rps < 0.0 {
+            return Err(format!("{ENV_RATE_LIMIT_RPS} must not be negative").into());
+        }
+        let rate_limit_key = match env::var(ENV_RATE_LIMIT_KEY) {
+            Ok(value) => RateLimitKey::parse(&value)
+                .ok_or_else(|| format!("{ENV_RATE_LIMIT_KEY} must be one of: ip, source"))?,
+            Err(_) => RateLimitKey::Ip,
+        };
+
+        let admin_token = match env::var(ENV_ADMIN_TOKEN) {
+            Ok(value) if value.trim().is_empty() => {
+                return Err(format!("{ENV_ADMIN_TOKEN} must not be empty").into());
+            }
+            Ok(value) => Some(value.trim().to_string()),
+            Err(_) => None,
+        };
+        let admin_persist = parse_env(ENV_ADMIN_PERSIST, false)?;
+        if admin_persist && rules_file.is_none() {
+            return Err(format!("{ENV_ADMIN_PERSIST} requires {ENV_RULES_FILE}").into());
         }
 
         Ok(Self {
-            activation_words,
-            stop_words,
+            stop_word_policy,
             bind_addr,
-            webhook_url,
+            rules_file,
+            rules_watch_interval: Duration::from_millis(parse_env(
+                ENV_RULES_WATCH_INTERVAL_MS,
+                2_000,
+            )?),
+            rule_defaults,
+            rules,
+            forward: ForwardSettings::from_env()?,
+            forward_mode,
+            queue_capacity: parse_env(ENV_QUEUE_CAPACITY, 64)?,
+            dedup_ttl: Duration::from_millis(parse_env(ENV_DEDUP_TTL_MS, 0)?),
+            listen_window: Duration::from_millis(parse_env(ENV_LISTEN_WINDOW_MS, 0)?),
+            min_activation_confidence,
+            webhook_auth,
+            webhook_secret,
+            rate_limit_rps,
+            rate_limit_burst: parse_env(ENV_RATE_LIMIT_BURST, 10)?,
+            rate_limit_key,
+            admin_token,
+            admin_persist,
+        })
+    }
+}
+
+impl Config {
+    pub(crate) fn match_options(&self) -> MatchOptions {
+        MatchOptions {
+            stop_word_policy: self.stop_word_policy,
+            min_activation_confidence: self.min_activation_confidence,
+        }
+    }
+}
+
+/// What `va-activator eval` needs: the word lists and how utterances are matched. Nothing about
+/// serving or forwarding has to be configured.
+pub(crate) struct EvalConfig {
+    pub(crate) rules: Rules,
+    pub(crate) match_options: MatchOptions,
+    /// Whether `LISTEN_WINDOW_MS` is set, which makes a bare activation word respond `listening`.
+    pub(crate) listen_window: bool,
+}
+
+impl EvalConfig {
+    pub(crate) fn from_env() -> Result<Self, Error> {
+        let rule_defaults = RuleDefaults {
+            // Eval never forwards, so any default route will do.
+            webhook_url: Some(EVAL_WEBHOOK_URL.to_string()),
+            activation_words: words_from_env(ENV_ACTIVATION_WORDS)?,
+            stop_words: words_from_env(ENV_STOP_WORDS)?,
+        };
+        let rules = match env::var(ENV_RULES_FILE) {
+            Ok(path) => Rules::load(Path::new(&path), &rule_defaults)?,
+            Err(_) => Rules::from_defaults(&rule_defaults)?,
+        };
+        let listen_window: u64 = parse_env(ENV_LISTEN_WINDOW_MS, 0)?;
+        Ok(Self {
+            rules,
+            match_options: match_options_from_env()?,
+            listen_window: listen_window > 0,
         })
     }
 }
+
+fn match_options_from_env() -> Result<MatchOptions, Error> {
+    let stop_word_policy = match env::var(ENV_STOP_WORD_POLICY) {
+        Ok(value) => StopWordPolicy::parse(&value).ok_or_else(|| {
+            format!("{ENV_STOP_WORD_POLICY} must be one of: only, start, anywhere")
+        })?,
+        Err(_) => StopWordPolicy::Only,
+    };
+    let min_activation_confidence = parse_env(ENV_MIN_ACTIVATION_CONFIDENCE, 0.0)?;
+    if !(0.0..=1.0).contains(&min_activation_confidence) {
+        return Err(format!("{ENV_MIN_ACTIVATION_CONFIDENCE} must be between 0 and 1").into());
+    }
+    Ok(MatchOptions {
+        stop_word_policy,
+        min_activation_confidence,
+    })
+}
+
+/// Reads a comma-separated word list. Unset yields `None`; a set but empty list is an error.
+fn words_from_env(name: &str) -> Result<Option<HashSet<String>>, Error> {
+    let Ok(raw) = env::var(name) else {
+        return Ok(None);
+    };
+    let words = raw
+        .split(',')
+        .map(normalize_word)
+        .filter(|word| !word.is_empty())
+        .collect::<HashSet<_>>();
+    if words.is_empty() {
+        return Err(format!("{name} must contain at least one word").into());
+    }
+    Ok(Some(words))
+}
+
+fn parse_env<T: FromStr>(name: &str, default: T) -> Result<T, Error> {
+    match env::var(name) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map_err(|_| format!("{name} has an invalid value: {value}").into()),
+        Err(_) => Ok(default),
+    }
+}
+
+pub(crate) fn normalize_word(word: &str) -> String {
+    word.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Remembers recently forwarded commands so the same utterance is not forwarded twice within the
+/// TTL.
+pub(crate) struct Deduplicator {
+    ttl: Duration,
+    seen: Mutex<HashMap<String, Instant>>,
+}
+
+impl Deduplicator {
+    pub(crate) fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn key(source: Option<&str>, command: &str) -> String {
+        format!("{}\n{command}", source.unwrap_or_default())
+    }
+
+    /// Records the key and reports whether it was already seen within the TTL. A zero TTL disables
+    /// suppression.
+    pub(crate) fn is_duplicate(&self, key: &str, now: Instant) -> bool {
+        if self.ttl.is_zero() {
+            return false;
+        }
+
+        let mut seen = self.seen.lock().unwrap();
+        seen.retain(|_, at| now.duration_since(*at) < self.ttl);
+        if seen.contains_key(key) {
+            return true;
+        }
+        seen.insert(key.to_string(), now);
+        false
+    }
+
+    /// Drops the key so a retry of a command that could not be delivered is not suppressed.
+    pub(crate) fn forget(&self, key: &str) {
+        self.seen.lock().unwrap().remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suppresses_repeats_within_window() {
+        let dedup = Deduplicator::new(Duration::from_secs(2));
+        let key = Deduplicator::key(Some("kitchen"), "turn off the lights");
+        let start = Instant::now();
+
+        assert!(!dedup.is_duplicate(&key, start));
+        assert!(dedup.is_duplicate(&key, start + Duration::from_millis(500)));
+        assert!(dedup.is_duplicate(&key, start + Duration::from_millis(1999)));
+    }
+
+    #[test]
+    fn window_expires_after_ttl() {
+        let dedup = Deduplicator::new(Duration::from_secs(2));
+        let key = Deduplicator::key(None, "turn off the lights");
+        let start = Instant::now();
+
+        assert!(!dedup.is_duplicate(&key, start));
+        assert!(!dedup.is_duplicate(&key, start + Duration::from_secs(2)));
+        assert!(dedup.is_duplicate(&key, start + Duration::from_secs(3)));
+        assert!(!dedup.is_duplicate(&key, start + Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn keys_include_source() {
+        let dedup = Deduplicator::new(Duration::from_secs(2));
+        let now = Instant::now();
+
+        assert!(!dedup.is_duplicate(&Deduplicator::key(Some("kitchen"), "lights off"), now));
+        assert!(!dedup.is_duplicate(&Deduplicator::key(Some("office"), "lights off"), now));
+        assert!(!dedup.is_duplicate(&Deduplicator::key(None, "lights off"), now));
+    }
+
+    #[test]
+    fn forgotten_keys_are_not_duplicates() {
+        let dedup = Deduplicator::new(Duration::from_secs(2));
+        let key = Deduplicator::key(None, "lights off");
+        let now = Instant::now();
+
+        assert!(!dedup.is_duplicate(&key, now));
+        dedup.forget(&key);
+        assert!(!dedup.is_duplicate(&key, now));
+    }
+
+    #[test]
+    fn zero_ttl_disables_suppression() {
+        let dedup = Deduplicator::new(Duration::ZERO);
+        let now = Instant::now();
+
+        assert!(!dedup.is_duplicate("lights off", now));
+        assert!(!dedup.is_duplicate("lights off", now));
+    }
+}
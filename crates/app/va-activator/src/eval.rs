@@ -0,0 +1,413 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+
+use serde::Deserialize;
+
+use crate::config::EvalConfig;
+use crate::error::Error;
+use crate::matcher::{normalize, Match, MatchOptions, Matcher, RecognizedWord};
+use crate::rules::Rules;
+
+/// Statuses that mean the activation word was taken.
+const ACTIVATED: [&str; 3] = ["accepted", "stopped", "listening"];
+
+/// One line of the evaluation file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Case {
+    text: String,
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default)]
+    words: Vec<RecognizedWord>,
+    /// The webhook status the utterance should get.
+    expected: String,
+    /// Required when `expected` is an activating status.
+    #[serde(default)]
+    activation_word: Option<String>,
+    /// Checked only when given.
+    #[serde(default)]
+    command: Option<String>,
+}
+
+struct Prediction {
+    status: &'static str,
+    activation_word: Option<String>,
+    command: Option<String>,
+}
+
+#[derive(Default)]
+struct Score {
+    true_positives: usize,
+    false_positives: usize,
+    false_negatives: usize,
+}
+
+struct Mismatch {
+    line: usize,
+    text: String,
+    expected: String,
+    actual: String,
+}
+
+/// Per activation word detection scores plus every case whose outcome differed from the
+/// expectation.
+pub(crate) struct Report {
+    cases: usize,
+    scores: BTreeMap<String, Score>,
+    mismatches: Vec<Mismatch>,
+}
+
+/// Runs `va-activator eval <file>` and returns the process exit code: `0` when every case matched,
+/// `1` when some did not, `2` when the file could not be evaluated.
+pub(crate) fn run(path: Option<String>) -> i32 {
+    let Some(path) = path else {
+        eprintln!("usage: va-activator eval <utterances.jsonl>");
+        return 2;
+    };
+    let report = EvalConfig::from_env()
+        .map_err(|err| format!("config error: {err}").into())
+        .and_then(|config| {
+            let input =
+                fs::read_to_string(&path).map_err(|err| format!("failed to read {path}: {err}"))?;
+            evaluate(
+                &input,
+                &config.rules,
+                config.match_options,
+                config.listen_window,
+            )
+        });
+    match report {
+        Ok(report) => {
+            print!("{report}");
+            if report.mismatches.is_empty() {
+                0
+            } else {
+                1
+            }
+        }
+        Err(err) => {
+            eprintln!("eval error: {err}");
+            2
+        }
+    }
+}
+
+/// Matches every case without per-source state: each utterance is judged on its own, as if no
+/// listening window were open and nothing had been heard before.
+pub(crate) fn evaluate(
+    input: &str,
+    rules: &Rules,
+    options: MatchOptions,
+    listening: bool,
+) -> Result<Report, Error> {
+    let mut report = Report {
+        cases: 0,
+        scores: BTreeMap::new(),
+        mismatches: Vec::new(),
+    };
+
+    for (index, line) in input.lines().enumerate() {
+        let line_number = index + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let case: Case =
+            serde_json::from_str(line).map_err(|err| format!("line {line_number}: {err}"))?;
+        let expected_activation = if ACTIVATED.contains(&case.expected.as_str()) {
+            let word = case.activation_word.as_deref().ok_or_else(|| {
+                format!(
+                    "line {line_number}: activation_word is required when expected is {}",
+                    case.expected
+                )
+            })?;
+            Some(normalize(word))
+        } else {
+            None
+        };
+
+        let matcher = Matcher::new(rules, case.source.as_deref(), options);
+        let prediction = predict(&matcher, &normalize(&case.text), &case.words, listening);
+        let predicted_activation = ACTIVATED
+            .contains(&prediction.status)
+            .then(|| prediction.activation_word.clone())
+            .flatten();
+
+        report.cases += 1;
+        report.score(
+            expected_activation.as_deref(),
+            predicted_activation.as_deref(),
+        );
+
+        let expected_command = case.command.as_deref().map(normalize);
+        let matches = prediction.status == case.expected
+            && expected_activation == predicted_activation
+            && expected_command
+                .as_ref()
+                .is_none_or(|command| prediction.command.as_ref() == Some(command));
+        if !matches {
+            report.mismatches.push(Mismatch {
+                line: line_number,
+                text: case.text,
+                expected: describe(
+                    &case.expected,
+                    expected_activation.as_deref(),
+                    expected_command.as_deref(),
+                ),
+                actual: describe(
+                    prediction.status,
+                    prediction.activation_word.as_deref(),
+                    prediction.command.as_deref(),
+                ),
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+fn predict(
+    matcher: &Matcher<'_>,
+    text: &str,
+    words: &[RecognizedWord],
+    listening: bool,
+) -> Prediction {
+    let prediction = |status, activation_word: String, command| Prediction {
+        status,
+        activation_word: Some(activation_word),
+        command,
+    };
+    match matcher.match_utterance(text, words) {
+        Match::NoActivation => Prediction {
+            status: "ignored",
+            activation_word: None,
+            command: None,
+        },
+        Match::LowConfidence {
+            activation_word, ..
+        } => prediction("low_confidence", activation_word, None),
+        Match::ActivationOnly { activation_word } => {
+            let status = if listening { "listening" } else { "ignored" };
+            prediction(status, activation_word, None)
+        }
+        Match::Stop {
+            activation_word,
+            command,
+        } => prediction("stopped", activation_word, Some(command)),
+        Match::Command {
+            activation_word,
+            command,
+        } => prediction("accepted", activation_word, Some(command)),
+    }
+}
+
+fn describe(status: &str, activation_word: Option<&str>, command: Option<&str>) -> String {
+    let mut description = status.to_string();
+    if let Some(activation_word) = activation_word {
+        description.push_str(&format!(" [{activation_word}]"));
+    }
+    if let Some(command) = command {
+        description.push_str(&format!(" {command:?}"));
+    }
+    description
+}
+
+impl Report {
+    fn score(&mut self, expected: Option<&str>, predicted: Option<&str>) {
+        if expected == predicted {
+            if let Some(word) = expected {
+                self.scores
+                    .entry(word.to_string())
+                    .or_default()
+                    .true_positives += 1;
+            }
+            return;
+        }
+        if let Some(word) = expected {
+            self.scores
+                .entry(word.to_string())
+                .or_default()
+                .false_negatives += 1;
+        }
+        if let Some(word) = predicted {
+            self.scores
+                .entry(word.to_string())
+                .or_default()
+                .false_positives += 1;
+        }
+    }
+}
+
+fn ratio(numerator: usize, denominator: usize) -> String {
+    if denominator == 0 {
+        "-".to_string()
+    } else {
+        format!("{:.2}", numerator as f64 / denominator as f64)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .scores
+            .keys()
+            .map(String::len)
+            .max()
+            .unwrap_or(0)
+            .max("activation word".len());
+        writeln!(
+            f,
+            "{:<width$}  precision  recall  tp  fp  fn",
+            "activation word"
+        )?;
+        for (word, score) in &self.scores {
+            writeln!(
+                f,
+                "{word:<width$}  {:>9}  {:>6}  {:>2}  {:>2}  {:>2}",
+                ratio(
+                    score.true_positives,
+                    score.true_positives + score.false_positives
+                ),
+                ratio(
+                    score.true_positives,
+                    score.true_positives + score.false_negatives
+                ),
+                score.true_positives,
+                score.false_positives,
+                score.false_negatives,
+            )?;
+        }
+
+        if !self.mismatches.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "mismatches:")?;
+            for mismatch in &self.mismatches {
+                writeln!(
+                    f,
+                    "  line {}: {:?}: expected {}, got {}",
+                    mismatch.line, mismatch.text, mismatch.expected, mismatch.actual
+                )?;
+            }
+        }
+
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{} of {} cases matched",
+            self.cases - self.mismatches.len(),
+            self.cases
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StopWordPolicy;
+    use crate::rules::RuleDefaults;
+
+    fn rules() -> Rules {
+        Rules::from_defaults(&RuleDefaults {
+            webhook_url: Some("http://127.0.0.1:8092/webhook".to_string()),
+            activation_words: Some(
+                ["va".to_string(), "computer".to_string()]
+                    .into_iter()
+                    .collect(),
+            ),
+            stop_words: Some(["stop".to_string()].into_iter().collect()),
+        })
+        .unwrap()
+    }
+
+    const OPTIONS: MatchOptions = MatchOptions {
+        stop_word_policy: StopWordPolicy::Only,
+        min_activation_confidence: 0.5,
+    };
+
+    const CASES: &str = r#"
+{"text": "VA play music", "expected": "accepted", "activation_word": "va", "command": "play music"}
+{"text": "va stop", "expected": "stopped", "activation_word": "va"}
+{"text": "computer lights on", "expected": "accepted", "activation_word": "computer"}
+{"text": "viva la vida", "expected": "ignored"}
+{"text": "va on the tv", "words": [{"word": "va", "conf": 0.2}], "expected": "low_confidence"}
+{"text": "va stop the music", "expected": "stopped", "activation_word": "va"}
+{"text": "computer", "expected": "ignored"}
+{"text": "commuter train", "expected": "accepted", "activation_word": "computer"}
+"#;
+
+    #[test]
+    fn scores_activation_words_and_lists_mismatches() {
+        let report = evaluate(CASES, &rules(), OPTIONS, false).unwrap();
+
+        assert_eq!(report.cases, 8);
+        let va = &report.scores["va"];
+        assert_eq!(
+            (va.true_positives, va.false_positives, va.false_negatives),
+            (3, 0, 0)
+        );
+        let computer = &report.scores["computer"];
+        assert_eq!(
+            (
+                computer.true_positives,
+                computer.false_positives,
+                computer.false_negatives
+            ),
+            (1, 0, 1)
+        );
+
+        let lines = report
+            .mismatches
+            .iter()
+            .map(|mismatch| mismatch.line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [7, 9]);
+        assert_eq!(
+            report.mismatches[0].actual,
+            r#"accepted [va] "stop the music""#
+        );
+
+        let output = report.to_string();
+        let computer_row = output
+            .lines()
+            .find(|line| line.starts_with("computer"))
+            .unwrap();
+        assert_eq!(
+            computer_row.split_whitespace().collect::<Vec<_>>(),
+            ["computer", "1.00", "0.50", "1", "0", "1"]
+        );
+        assert!(output.contains("6 of 8 cases matched"), "{output}");
+    }
+
+    #[test]
+    fn bare_activation_word_listens_with_listening_windows() {
+        let input =
+            r#"{"text": "computer", "expected": "listening", "activation_word": "computer"}"#;
+
+        assert!(evaluate(input, &rules(), OPTIONS, true)
+            .unwrap()
+            .mismatches
+            .is_empty());
+        assert_eq!(
+            evaluate(input, &rules(), OPTIONS, false)
+                .unwrap()
+                .mismatches
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_cases() {
+        let missing_word = r#"{"text": "va play", "expected": "accepted"}"#;
+        let err = evaluate(missing_word, &rules(), OPTIONS, false)
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .contains("line 1: activation_word is required"));
+
+        let unknown_field = r#"{"text": "va play", "expected": "ignored", "status": "ignored"}"#;
+        assert!(evaluate(unknown_field, &rules(), OPTIONS, false).is_err());
+    }
+}
@@ -0,0 +1,76 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use actix_web::web::Bytes;
+use futures_util::Stream;
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::warn;
+
+/// Frames buffered per subscriber; a subscriber that falls further behind skips the oldest ones.
+const BUFFER: usize = 256;
+/// Interval of SSE comments sent while no decision happens, so idle connections are not dropped by
+/// proxies.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// One webhook decision as published on `GET /events`.
+#[derive(Serialize)]
+pub(crate) struct DecisionEvent<'a> {
+    pub(crate) status: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) command: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) activation_word: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) source: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) speaker: Option<&'a str>,
+    pub(crate) request_id: &'a str,
+    pub(crate) received_at: &'a str,
+}
+
+/// Fans decisions out to the connected SSE clients.
+pub(crate) struct Events {
+    sender: broadcast::Sender<Bytes>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(BUFFER).0,
+        }
+    }
+}
+
+impl Events {
+    /// Encodes the event once as an SSE frame and hands it to every subscriber. Does nothing
+    /// without subscribers.
+    pub(crate) fn publish(&self, event: &DecisionEvent<'_>) {
+        if self.sender.receiver_count() == 0 {
+            return;
+        }
+        match serde_json::to_string(event) {
+            Ok(json) => {
+                let _ = self.sender.send(Bytes::from(format!("data: {json}\n\n")));
+            }
+            Err(err) => warn!("failed to encode decision event: {err}"),
+        }
+    }
+
+    /// Returns the SSE body for a new subscriber, starting with the next published decision.
+    pub(crate) fn subscribe(&self) -> impl Stream<Item = Result<Bytes, Infallible>> + 'static {
+        let receiver = self.sender.subscribe();
+        futures_util::stream::unfold(receiver, |mut receiver| async move {
+            let frame = match actix_web::rt::time::timeout(KEEP_ALIVE, receiver.recv()).await {
+                Ok(Ok(frame)) => frame,
+                Ok(Err(RecvError::Lagged(skipped))) => {
+                    warn!("event subscriber lagged, skipped {skipped} decisions");
+                    Bytes::from(format!(": skipped {skipped} decisions\n\n"))
+                }
+                Ok(Err(RecvError::Closed)) => return None,
+                Err(_) => Bytes::from_static(b": keep-alive\n\n"),
+            };
+            Some((Ok(frame), receiver))
+        })
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use reqwest::StatusCode;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::config::ForwardSettings;
+use crate::error::Error;
+use crate::rules::Route;
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ForwardEvent {
+    Command,
+    Cancel,
+}
+
+pub(crate) const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Body sent downstream. Receivers that only read `text` keep working; everything else is metadata.
+#[derive(Serialize)]
+pub(crate) struct ForwardPayload {
+    pub(crate) event: ForwardEvent,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) text: Option<String>,
+    /// The utterance as received, before normalization.
+    pub(crate) original_text: String,
+    pub(crate) activation_word: String,
+    /// RFC 3339 time at which the activator received the utterance.
+    pub(crate) received_at: String,
+    /// Also sent as the `X-Request-Id` header.
+    pub(crate) request_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) speaker: Option<String>,
+    /// Name of the `[[intents]]` pattern the command matched.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) intent: Option<String>,
+    /// Text captured by the intent's `{slot}` placeholders.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) slots: BTreeMap<String, String>,
+}
+
+#[derive(Debug)]
+pub(crate) enum ForwardError {
+    /// The route's circuit breaker is open; nothing was sent.
+    Unavailable,
+    Request(reqwest::Error),
+}
+
+impl fmt::Display for ForwardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unavailable => f.write_str("circuit breaker is open"),
+            Self::Request(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Breaker {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// Sends events downstream with bounded retries and a circuit breaker per route.
+pub(crate) struct Forwarder {
+    client: reqwest::Client,
+    settings: ForwardSettings,
+    breakers: Mutex<HashMap<String, Breaker>>,
+}
+
+impl Forwarder {
+    pub(crate) fn new(settings: ForwardSettings) -> Result<Self, Error> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(settings.connect_timeout)
+            .timeout(settings.request_timeout)
+            .build()?;
+        Ok(Self {
+            client,
+            settings,
+            breakers: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub(crate) async fn send(
+        &self,
+        route: &Route,
+        payload: &ForwardPayload,
+    ) -> Result<(), ForwardError> {
+        if !self.acquire(route) {
+            return Err(ForwardError::Unavailable);
+        }
+
+        let mut attempt = 0;
+        let result = loop {
+            match self.post(route, payload).await {
+                Err(err) if attempt < self.settings.max_retries && is_retryable(&err) => {
+                    let backoff = self.settings.retry_backoff * 2u32.saturating_pow(attempt);
+                    warn!(
+                        "route {} failed ({err}), retrying in {backoff:?}",
+                        route.name
+                    );
+                    actix_web::rt::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+                result => break result,
+            }
+        };
+
+        let downstream_failed = match &result {
+            Ok(()) => false,
+            Err(err) => err.status().is_none_or(|status| status.is_server_error()),
+        };
+        self.record(route, downstream_failed);
+        result.map_err(ForwardError::Request)
+    }
+
+    async fn post(&self, route: &Route, payload: &ForwardPayload) -> Result<(), reqwest::Error> {
+        let mut request = self
+            .client
+            .post(&route.url)
+            .headers(route.headers.clone())
+            .header(REQUEST_ID_HEADER, &payload.request_id)
+            .json(payload);
+        if let Some(timeout) = route.timeout {
+            request = request.timeout(timeout);
+        }
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    /// Checks the route's breaker. Once the cooldown has passed, a single trial request is let
+    /// through and the breaker stays open for everyone else until that request has been recorded.
+    fn acquire(&self, route: &Route) -> bool {
+        if self.settings.breaker_threshold == 0 {
+            return true;
+        }
+
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers.entry(route.name.clone()).or_default();
+        let now = Instant::now();
+        match breaker.open_until {
+            Some(until) if now < until => false,
+            Some(_) => {
+                breaker.open_until = Some(now + self.settings.breaker_cooldown);
+                true
+            }
+            None => true,
+        }
+    }
+
+    fn record(&self, route: &Route, failed: bool) {
+        if self.settings.breaker_threshold == 0 {
+            return;
+        }
+
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers.entry(route.name.clone()).or_default();
+        if !failed {
+            *breaker = Breaker::default();
+            return;
+        }
+
+        breaker.consecutive_failures += 1;
+        if breaker.consecutive_failures >= self.settings.breaker_threshold {
+            if breaker.open_until.is_none() {
+                warn!("route {} circuit breaker opened", route.name);
+            }
+            breaker.open_until = Some(Instant::now() + self.settings.breaker_cooldown);
+        }
+    }
+}
+
+/// Only failures where the downstream did not act on the request are retried: the connection could
+/// not be established, or a gateway in front of the downstream reported it unavailable.
+fn is_retryable(err: &reqwest::Error) -> bool {
+    if err.is_connect() {
+        return true;
+    }
+    matches!(
+        err.status(),
+        Some(
+            StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+        )
+    )
+}
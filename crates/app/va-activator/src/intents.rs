@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+/// `{name}` placeholders in intent patterns. Regex repetitions such as `{2}` or `{1,3}` are left
+/// alone.
+static SLOT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{([a-z_][a-z0-9_]*)\}").unwrap());
+
+/// `[[intents]]` entry of the rules file.
+///
+/// ```toml
+/// [[intents]]
+/// name = "set_timer"
+/// pattern = "set (a )?timer for {duration}"
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct IntentEntry {
+    name: String,
+    pattern: String,
+}
+
+/// A command pattern with named slots. The pattern is a regex over the whole command in which
+/// `{slot}` matches any text and captures it under that name.
+#[derive(Clone, Debug)]
+pub(crate) struct Intent {
+    name: String,
+    pattern: String,
+    regex: Regex,
+}
+
+/// The intent a command matched and the text captured by its slots.
+#[derive(Debug, PartialEq)]
+pub(crate) struct IntentMatch {
+    pub(crate) name: String,
+    pub(crate) slots: BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct IntentView<'a> {
+    name: &'a str,
+    pattern: &'a str,
+}
+
+impl Intent {
+    pub(crate) fn from_entry(entry: IntentEntry) -> Result<Self, Error> {
+        let name = entry.name.trim().to_string();
+        if name.is_empty() {
+            return Err("intent name must not be empty".into());
+        }
+        let regex = compile(&entry.pattern)
+            .map_err(|err| format!("intent {name}: invalid pattern: {err}"))?;
+        Ok(Self {
+            name,
+            pattern: entry.pattern,
+            regex,
+        })
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn view(&self) -> IntentView<'_> {
+        IntentView {
+            name: &self.name,
+            pattern: &self.pattern,
+        }
+    }
+}
+
+fn compile(pattern: &str) -> Result<Regex, regex::Error> {
+    let pattern = SLOT.replace_all(pattern.trim(), "(?P<$1>.+?)");
+    Regex::new(&format!("(?i)^(?:{pattern})$"))
+}
+
+/// Returns the first intent in file order whose pattern matches the whole command.
+pub(crate) fn match_intent(intents: &[Intent], command: &str) -> Option<IntentMatch> {
+    intents.iter().find_map(|intent| {
+        let captures = intent.regex.captures(command)?;
+        let slots = intent
+            .regex
+            .capture_names()
+            .flatten()
+            .filter_map(|slot| {
+                let value = captures.name(slot)?.as_str().trim();
+                Some((slot.to_string(), value.to_string()))
+            })
+            .collect();
+        Some(IntentMatch {
+            name: intent.name.clone(),
+            slots,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn intent(name: &str, pattern: &str) -> Intent {
+        Intent::from_entry(IntentEntry {
+            name: name.to_string(),
+            pattern: pattern.to_string(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn captures_slots() {
+        let intents = [
+            intent("set_timer", "set (a )?timer for {duration}"),
+            intent("play", "play {title} by {artist}"),
+        ];
+
+        let matched = match_intent(&intents, "set a timer for ten minutes").unwrap();
+        assert_eq!(matched.name, "set_timer");
+        assert_eq!(matched.slots["duration"], "ten minutes");
+        assert_eq!(
+            match_intent(&intents, "set timer for 5 minutes")
+                .unwrap()
+                .slots["duration"],
+            "5 minutes"
+        );
+
+        let matched = match_intent(&intents, "play yellow submarine by the beatles").unwrap();
+        assert_eq!(matched.slots["title"], "yellow submarine");
+        assert_eq!(matched.slots["artist"], "the beatles");
+    }
+
+    #[test]
+    fn matches_whole_command_only() {
+        let intents = [intent("lights_on", "turn on the lights")];
+
+        assert!(match_intent(&intents, "turn on the lights").is_some());
+        assert!(match_intent(&intents, "please turn on the lights").is_none());
+        assert!(match_intent(&intents, "turn on the lights now").is_none());
+    }
+
+    #[test]
+    fn keeps_regex_repetitions() {
+        let intents = [intent("code", "code [0-9]{4} for {door}")];
+
+        let matched = match_intent(&intents, "code 1234 for front door").unwrap();
+        assert_eq!(matched.slots["door"], "front door");
+        assert!(match_intent(&intents, "code 12 for front door").is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        for pattern in ["set (timer", "{slot} and {slot}"] {
+            let entry = IntentEntry {
+                name: "broken".to_string(),
+                pattern: pattern.to_string(),
+            };
+            assert!(Intent::from_entry(entry).is_err(), "{pattern}");
+        }
+    }
+}
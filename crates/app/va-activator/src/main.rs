@@ -1,22 +1,99 @@
+mod admin;
+mod auth;
 mod config;
+mod dedup;
 mod error;
+mod eval;
+mod events;
+mod forward;
+mod intents;
+mod matcher;
+mod metrics;
+mod queue;
+mod ratelimit;
+mod reload;
+mod rules;
+mod sources;
 
-use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::http::{header, StatusCode};
+use actix_web::{get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::BTreeMap;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Instant;
 use tracing::{info, warn};
 use tracing_subscriber::EnvFilter;
 
-use crate::config::Config;
+use crate::auth::WebhookAuth;
+use crate::config::{Config, ForwardMode, RateLimitKey};
+use crate::dedup::Deduplicator;
+use crate::error::Error;
+use crate::events::{DecisionEvent, Events};
+use crate::forward::{ForwardError, ForwardEvent, ForwardPayload, Forwarder, REQUEST_ID_HEADER};
+use crate::matcher::{normalize, Match, Matcher, RecognizedWord};
+use crate::metrics::Metrics;
+use crate::queue::ForwardQueue;
+use crate::ratelimit::RateLimiter;
+use crate::reload::RuleStore;
+use crate::rules::Route;
+use crate::sources::Sources;
+
+const DEFAULT_SOURCE: &str = "default";
 
 struct AppState {
     config: Config,
-    client: reqwest::Client,
+    rules: Arc<RuleStore>,
+    forwarder: Arc<Forwarder>,
+    queue: ForwardQueue,
+    metrics: Arc<Metrics>,
+    dedup: Deduplicator,
+    sources: Sources,
+    events: Events,
+    auth: WebhookAuth,
+    rate_limiter: RateLimiter,
+}
+
+impl AppState {
+    fn new(config: Config) -> Result<Self, Error> {
+        let forwarder = Arc::new(Forwarder::new(config.forward.clone())?);
+        let metrics = Arc::new(Metrics::default());
+        let queue = ForwardQueue::new(forwarder.clone(), metrics.clone(), config.queue_capacity);
+        let dedup = Deduplicator::new(config.dedup_ttl);
+        let rules = Arc::new(RuleStore::new(
+            config.rules_file.clone(),
+            config.rule_defaults.clone(),
+            config.rules.clone(),
+        ));
+        let auth = WebhookAuth::new(
+            config.webhook_auth,
+            config.webhook_secret.as_deref().unwrap_or_default(),
+        );
+        let rate_limiter = RateLimiter::new(config.rate_limit_rps, config.rate_limit_burst);
+        Ok(Self {
+            config,
+            rules,
+            forwarder,
+            queue,
+            metrics,
+            dedup,
+            sources: Sources::default(),
+            events: Events::default(),
+            auth,
+            rate_limiter,
+        })
+    }
 }
 
 #[derive(Deserialize)]
 struct WebhookRequest {
     text: String,
+    /// Identifies the audio source, e.g. the room a va-voice instance runs in.
+    source: Option<String>,
+    speaker: Option<String>,
+    /// Per-word recognizer results in the order they were spoken, as reported by Vosk.
+    #[serde(default)]
+    words: Vec<RecognizedWord>,
 }
 
 #[derive(Serialize)]
@@ -39,6 +116,19 @@ async fn main() -> std::io::Result<()> {
         .with_writer(std::io::stderr)
         .init();
 
+    if let Some(command) = std::env::args().nth(1) {
+        let code = match command.as_str() {
+            "eval" => eval::run(std::env::args().nth(2)),
+            _ => {
+                eprintln!(
+                    "unknown command: {command}\nusage: va-activator [eval <utterances.jsonl>]"
+                );
+                2
+            }
+        };
+        std::process::exit(code);
+    }
+
     let config = match Config::from_env() {
         Ok(config) => config,
         Err(err) => {
@@ -50,16 +140,34 @@ async fn main() -> std::io::Result<()> {
     let bind_addr = config.bind_addr.clone();
     info!("va-activator listening on {bind_addr}");
 
-    let app_state = web::Data::new(AppState {
-        config,
-        client: reqwest::Client::new(),
-    });
+    let app_state = match AppState::new(config) {
+        Ok(state) => web::Data::new(state),
+        Err(err) => {
+            eprintln!("startup error: {err}");
+            std::process::exit(1);
+        }
+    };
+
+    if app_state.config.rules_file.is_some() {
+        let interval = app_state.config.rules_watch_interval;
+        if !interval.is_zero() {
+            actix_web::rt::spawn(reload::watch(app_state.rules.clone(), interval));
+        }
+        #[cfg(unix)]
+        actix_web::rt::spawn(reload::reload_on_sighup(app_state.rules.clone()));
+    }
 
     HttpServer::new(move || {
         App::new()
             .app_data(app_state.clone())
             .service(health)
+            .service(active_config)
+            .service(metrics_snapshot)
+            .service(event_stream)
             .service(webhook)
+            .service(admin::get_words)
+            .service(admin::replace_words)
+            .service(admin::remove_words)
     })
     .bind(bind_addr)?
     .run()
@@ -71,140 +179,456 @@ async fn health() -> impl Responder {
     web::Json(HealthResponse { status: "ok" })
 }
 
+#[get("/metrics")]
+async fn metrics_snapshot(state: web::Data<AppState>) -> impl Responder {
+    web::Json(state.metrics.snapshot(state.queue.capacity()))
+}
+
+/// Needs the admin token, since the rules carry route URLs and every word list.
+#[get("/config")]
+async fn active_config(state: web::Data<AppState>, request: HttpRequest) -> HttpResponse {
+    if let Err(response) = admin::authorize(&state, &request) {
+        return response;
+    }
+    let rules = state.rules.current();
+    HttpResponse::Ok().json(rules.view())
+}
+
+/// Needs the admin token, since the stream carries everything users say.
+#[get("/events")]
+async fn event_stream(state: web::Data<AppState>, request: HttpRequest) -> HttpResponse {
+    if let Err(response) = admin::authorize(&state, &request) {
+        return response;
+    }
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(state.events.subscribe())
+}
+
 #[post("/webhook")]
 async fn webhook(
     state: web::Data<AppState>,
-    payload: web::Json<WebhookRequest>,
+    request: HttpRequest,
+    body: web::Bytes,
 ) -> HttpResponse {
-    let text = normalize(&payload.text);
-    if text.is_empty() {
-        return HttpResponse::Ok().json(WebhookResponse {
-            status: "ignored",
+    // Limit by address before looking at the request, so floods of unauthenticated or malformed
+    // requests are limited too. With `RATE_LIMIT_KEY=source` only rejected requests count against
+    // their address, since a proxy in front would otherwise put every source in one bucket.
+    let peer = request
+        .peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_default();
+    let by_source = state.config.rate_limit_key == RateLimitKey::Source;
+    if !by_source {
+        if let Err(response) = check_rate_limit(&state, &peer) {
+            return response;
+        }
+    }
+
+    if !state.auth.verify(request.headers(), &body) {
+        if by_source {
+            if let Err(response) = check_rate_limit(&state, &peer) {
+                return response;
+            }
+        }
+        warn!("rejected webhook request without valid credentials");
+        state.metrics.unauthorized.fetch_add(1, Ordering::Relaxed);
+        return HttpResponse::Unauthorized().json(WebhookResponse {
+            status: "unauthorized",
             command: None,
         });
     }
-
-    let activation_word = match find_activation_word(&text, &state.config.activation_words) {
-        Some(word) => word,
-        None => {
-            return HttpResponse::Ok().json(WebhookResponse {
-                status: "ignored",
+    let payload: WebhookRequest = match serde_json::from_slice(&body) {
+        Ok(payload) => payload,
+        Err(err) => {
+            if by_source {
+                if let Err(response) = check_rate_limit(&state, &peer) {
+                    return response;
+                }
+            }
+            warn!("invalid webhook request: {err}");
+            return HttpResponse::BadRequest().json(WebhookResponse {
+                status: "invalid_request",
                 command: None,
             });
         }
     };
+    if by_source {
+        let source = payload.source.as_deref().unwrap_or(DEFAULT_SOURCE);
+        if let Err(response) = check_rate_limit(&state, source) {
+            return response;
+        }
+    }
+
+    let received_at = chrono::Utc::now().to_rfc3339();
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+        .map_or_else(|| uuid::Uuid::new_v4().to_string(), str::to_string);
+
+    let outcome = decide(&state, &payload, &received_at, &request_id).await;
+    state.events.publish(&DecisionEvent {
+        status: outcome.status,
+        command: outcome.command.as_deref(),
+        activation_word: outcome.activation_word.as_deref(),
+        source: payload.source.as_deref(),
+        speaker: payload.speaker.as_deref(),
+        request_id: &request_id,
+        received_at: &received_at,
+    });
+    HttpResponse::build(outcome.http_status).json(WebhookResponse {
+        status: outcome.status,
+        command: outcome.command,
+    })
+}
 
-    let command_text = text[activation_word.len()..].trim();
-    if command_text.is_empty() {
-        return HttpResponse::Ok().json(WebhookResponse {
-            status: "ignored",
+/// Takes a token for the key, or builds the `429` response when it has none left.
+fn check_rate_limit(state: &AppState, key: &str) -> Result<(), HttpResponse> {
+    let Err(retry_after) = state.rate_limiter.check(key, Instant::now()) else {
+        return Ok(());
+    };
+    warn!("rate limit exceeded for {key}");
+    state.metrics.rate_limited.fetch_add(1, Ordering::Relaxed);
+    Err(HttpResponse::TooManyRequests()
+        .insert_header((
+            header::RETRY_AFTER,
+            retry_after.as_secs_f64().ceil().max(1.0).to_string(),
+        ))
+        .json(WebhookResponse {
+            status: "rate_limited",
             command: None,
-        });
-    }
+        }))
+}
 
-    let command_text = command_text.to_string();
-    if contains_stop_word(&command_text, &state.config.stop_words) {
-        info!("stop word detected");
-        return HttpResponse::Ok().json(WebhookResponse {
-            status: "stopped",
+/// What the webhook decided for one utterance. It is returned to the caller and published on
+/// `/events`.
+struct Outcome {
+    http_status: StatusCode,
+    status: &'static str,
+    command: Option<String>,
+    activation_word: Option<String>,
+}
+
+impl Outcome {
+    fn new(http_status: StatusCode, status: &'static str) -> Self {
+        Self {
+            http_status,
+            status,
             command: None,
-        });
+            activation_word: None,
+        }
     }
 
-    if let Err(err) = forward_command(&state.client, &state.config.webhook_url, &command_text).await {
-        warn!("webhook forward error: {err:?}");
-        return HttpResponse::BadGateway().json(WebhookResponse {
-            status: "error",
-            command: Some(command_text),
-        });
+    fn activation(mut self, activation_word: &str, command: Option<&str>) -> Self {
+        self.activation_word = Some(activation_word.to_string());
+        self.command = command.map(str::to_string);
+        self
     }
-
-    info!("activation detected");
-    HttpResponse::Ok().json(WebhookResponse {
-        status: "accepted",
-        command: Some(command_text),
-    })
 }
 
-fn normalize(input: &str) -> String {
-    input.trim().to_lowercase()
-}
+/// Decides what to do with one utterance and forwards the resulting event, if any.
+async fn decide(
+    state: &AppState,
+    payload: &WebhookRequest,
+    received_at: &str,
+    request_id: &str,
+) -> Outcome {
+    let text = normalize(&payload.text);
+    if text.is_empty() {
+        return Outcome::new(StatusCode::OK, "ignored");
+    }
+
+    let now = Instant::now();
+    let source = payload.source.as_deref().unwrap_or(DEFAULT_SOURCE);
+    let rules = state.rules.current();
+    let matcher = Matcher::new(
+        &rules,
+        payload.source.as_deref(),
+        state.config.match_options(),
+    );
 
-fn find_activation_word<'a>(text: &str, words: &'a HashSet<String>) -> Option<&'a str> {
-    for word in words {
-        if text == word {
-            return Some(word.as_str());
+    let matched = match matcher.match_utterance(&text, &payload.words) {
+        Match::NoActivation => match state.sources.take_window(source, now) {
+            Some(word) => matcher.match_command(&word, &text),
+            None => Match::NoActivation,
+        },
+        matched => matched,
+    };
+    let (activation_word, command_text, stop) = match matched {
+        Match::NoActivation => return Outcome::new(StatusCode::OK, "ignored"),
+        Match::LowConfidence {
+            activation_word,
+            confidence,
+        } => {
+            info!("activation word confidence {confidence} below threshold");
+            state.metrics.low_confidence.fetch_add(1, Ordering::Relaxed);
+            return Outcome::new(StatusCode::OK, "low_confidence")
+                .activation(&activation_word, None);
         }
-        if text.starts_with(word) && text.as_bytes().get(word.len()) == Some(&b' ') {
-            return Some(word.as_str());
+        Match::ActivationOnly { activation_word } => {
+            if state.config.listen_window.is_zero() {
+                return Outcome::new(StatusCode::OK, "ignored").activation(&activation_word, None);
+            }
+            info!("listening on source {source}");
+            state
+                .sources
+                .open_window(source, &activation_word, now, state.config.listen_window);
+            return Outcome::new(StatusCode::OK, "listening").activation(&activation_word, None);
+        }
+        Match::Stop {
+            activation_word,
+            command,
+        } => (activation_word, command, true),
+        Match::Command {
+            activation_word,
+            command,
+        } => (activation_word, command, false),
+    };
+    let command_text = command_text.as_str();
+
+    // A repeated stop has to go through, since the user may be cancelling something new.
+    let dedup_key = Deduplicator::key(payload.source.as_deref(), command_text);
+    if !stop && state.dedup.is_duplicate(&dedup_key, now) {
+        info!("duplicate utterance suppressed");
+        state.metrics.duplicates.fetch_add(1, Ordering::Relaxed);
+        return Outcome::new(StatusCode::OK, "duplicate")
+            .activation(&activation_word, Some(command_text));
+    }
+
+    let route = rules.routes.resolve(&activation_word, command_text);
+    let forward_payload = |event, text| ForwardPayload {
+        event,
+        text,
+        original_text: payload.text.clone(),
+        activation_word: activation_word.clone(),
+        received_at: received_at.to_string(),
+        request_id: request_id.to_string(),
+        source: payload.source.clone(),
+        speaker: payload.speaker.clone(),
+        intent: None,
+        slots: BTreeMap::new(),
+    };
+    if stop {
+        info!("stop word detected");
+        let cancel = forward_payload(ForwardEvent::Cancel, None);
+        if let Err(err) = deliver(state, source, route, cancel).await {
+            warn!("webhook cancel error on route {}: {err}", route.name);
         }
+        return Outcome::new(StatusCode::OK, "stopped").activation(&activation_word, None);
+    }
+
+    let mut command = forward_payload(ForwardEvent::Command, Some(command_text.to_string()));
+    if let Some(intent) = rules.match_intent(command_text) {
+        info!("command matched intent {}", intent.name);
+        command.intent = Some(intent.name);
+        command.slots = intent.slots;
+    }
+    let delivery = deliver(state, source, route, command).await;
+    if delivery.is_err() {
+        state.dedup.forget(&dedup_key);
     }
-    None
+    let outcome = match delivery {
+        Ok(Delivery::Forwarded) => {
+            info!("activation detected, forwarded to route {}", route.name);
+            Outcome::new(StatusCode::OK, "accepted")
+        }
+        Ok(Delivery::Queued) => {
+            info!("activation detected, queued for route {}", route.name);
+            Outcome::new(StatusCode::ACCEPTED, "accepted")
+        }
+        Err(DeliveryError::QueueFull) => {
+            warn!("forward queue is full, dropping command");
+            Outcome::new(StatusCode::SERVICE_UNAVAILABLE, "queue_full")
+        }
+        Err(DeliveryError::Forward(ForwardError::Unavailable)) => {
+            warn!("route {} is unavailable, dropping command", route.name);
+            Outcome::new(StatusCode::SERVICE_UNAVAILABLE, "downstream_unavailable")
+        }
+        Err(DeliveryError::Forward(err)) => {
+            warn!("webhook forward error on route {}: {err}", route.name);
+            Outcome::new(StatusCode::BAD_GATEWAY, "error")
+        }
+    };
+    outcome.activation(&activation_word, Some(command_text))
 }
 
-fn contains_stop_word(text: &str, stop_words: &HashSet<String>) -> bool {
-    text.split_whitespace()
-        .any(|token| stop_words.contains(token))
+enum Delivery {
+    Forwarded,
+    Queued,
 }
 
-async fn forward_command(
-    client: &reqwest::Client,
-    webhook_url: &str,
-    command: &str,
-) -> Result<(), reqwest::Error> {
-    client
-        .post(webhook_url)
-        .json(&serde_json::json!({ "text": command }))
-        .send()
-        .await?
-        .error_for_status()?;
-    Ok(())
+#[derive(Debug)]
+enum DeliveryError {
+    QueueFull,
+    Forward(ForwardError),
+}
+
+impl std::fmt::Display for DeliveryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::QueueFull => f.write_str("forward queue is full"),
+            Self::Forward(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+/// Sends the payload downstream directly or through the queue, depending on `FORWARD_MODE`.
+async fn deliver(
+    state: &AppState,
+    source: &str,
+    route: &Route,
+    payload: ForwardPayload,
+) -> Result<Delivery, DeliveryError> {
+    match state.config.forward_mode {
+        ForwardMode::Sync => {
+            let result = state.forwarder.send(route, &payload).await;
+            let counter = match result {
+                Ok(()) => &state.metrics.forwarded,
+                Err(_) => &state.metrics.forward_failures,
+            };
+            counter.fetch_add(1, Ordering::Relaxed);
+            result
+                .map(|()| Delivery::Forwarded)
+                .map_err(DeliveryError::Forward)
+        }
+        ForwardMode::Async => state
+            .queue
+            .push(source, route.clone(), payload)
+            .map(|()| Delivery::Queued)
+            .map_err(|_| DeliveryError::QueueFull),
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::needless_borrows_for_generic_args)]
 mod tests {
     use super::*;
+    use crate::config::{ForwardSettings, StopWordPolicy, WebhookAuthMode};
+    use crate::rules::{RuleDefaults, Rules};
+    use actix_web::body::MessageBody;
+    use actix_web::http::Method;
     use actix_web::{test, web, App, HttpResponse, HttpServer};
-    use std::sync::{Arc, Mutex};
     use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
 
-    #[derive(Deserialize)]
-    struct DownstreamRequest {
-        text: String,
-    }
+    type Received = Arc<Mutex<Vec<serde_json::Value>>>;
 
     fn test_config(webhook_url: String) -> Config {
+        let rule_defaults = RuleDefaults {
+            webhook_url: Some(webhook_url),
+            activation_words: Some(
+                ["va".to_string(), "assistant".to_string()]
+                    .into_iter()
+                    .collect(),
+            ),
+            stop_words: Some(
+                ["stop".to_string(), "cancel".to_string()]
+                    .into_iter()
+                    .collect(),
+            ),
+        };
         Config {
-            activation_words: ["va".to_string(), "assistant".to_string()]
-                .into_iter()
-                .collect(),
-            stop_words: ["stop".to_string(), "cancel".to_string()]
-                .into_iter()
-                .collect(),
+            stop_word_policy: StopWordPolicy::Only,
             bind_addr: "127.0.0.1:0".to_string(),
-            webhook_url,
+            rules_file: None,
+            rules_watch_interval: Duration::ZERO,
+            rules: Rules::from_defaults(&rule_defaults).unwrap(),
+            rule_defaults,
+            forward: ForwardSettings {
+                connect_timeout: Duration::from_secs(1),
+                request_timeout: Duration::from_secs(5),
+                max_retries: 0,
+                retry_backoff: Duration::ZERO,
+                breaker_threshold: 0,
+                breaker_cooldown: Duration::from_secs(30),
+            },
+            forward_mode: ForwardMode::Sync,
+            queue_capacity: 16,
+            dedup_ttl: Duration::ZERO,
+            listen_window: Duration::ZERO,
+            min_activation_confidence: 0.0,
+            webhook_auth: WebhookAuthMode::None,
+            webhook_secret: None,
+            rate_limit_rps: 0.0,
+            rate_limit_burst: 10,
+            rate_limit_key: RateLimitKey::Ip,
+            admin_token: None,
+            admin_persist: false,
         }
     }
 
-    async fn start_downstream() -> (String, Arc<Mutex<Vec<String>>>, actix_web::dev::ServerHandle) {
-        let received = Arc::new(Mutex::new(Vec::<String>::new()));
+    fn forwarded_events(received: &Received) -> Vec<String> {
+        received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|payload| payload["event"].as_str().unwrap_or_default().to_string())
+            .collect()
+    }
+
+    fn forwarded_texts(received: &Received) -> Vec<String> {
+        received
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|payload| payload["event"] == "command")
+            .map(|payload| payload["text"].as_str().unwrap_or_default().to_string())
+            .collect()
+    }
+
+    async fn start_downstream() -> (String, Received, actix_web::dev::ServerHandle) {
+        let (url, received, _attempts, handle) = start_failing_downstream(0).await;
+        (url, received, handle)
+    }
+
+    /// Starts a downstream that answers the first `fail_first` requests with `503 Service
+    /// Unavailable`. Only successful requests are recorded, with the `X-Request-Id` header added to
+    /// the body under its name; `attempts` counts every request.
+    async fn start_failing_downstream(
+        fail_first: usize,
+    ) -> (
+        String,
+        Received,
+        Arc<AtomicUsize>,
+        actix_web::dev::ServerHandle,
+    ) {
+        let received = Received::default();
         let received_clone = received.clone();
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let attempts_clone = attempts.clone();
         let listener = TcpListener::bind("127.0.0.1:0").unwrap();
         let addr = listener.local_addr().unwrap();
 
         let server = HttpServer::new(move || {
             let received = received_clone.clone();
+            let attempts = attempts_clone.clone();
             App::new().route(
                 "/webhook",
-                web::post().to(move |payload: web::Json<DownstreamRequest>| {
-                    let received = received.clone();
-                    async move {
-                        received.lock().unwrap().push(payload.text.clone());
-                        HttpResponse::Ok().finish()
-                    }
-                }),
+                web::post().to(
+                    move |request: HttpRequest, payload: web::Json<serde_json::Value>| {
+                        let received = received.clone();
+                        let attempts = attempts.clone();
+                        async move {
+                            if attempts.fetch_add(1, Ordering::SeqCst) < fail_first {
+                                return HttpResponse::ServiceUnavailable().finish();
+                            }
+                            let mut payload = payload.into_inner();
+                            if let Some(request_id) = request.headers().get(REQUEST_ID_HEADER) {
+                                payload[REQUEST_ID_HEADER] = request_id.to_str().unwrap().into();
+                            }
+                            received.lock().unwrap().push(payload);
+                            HttpResponse::Ok().finish()
+                        }
+                    },
+                ),
             )
         })
+        .shutdown_timeout(1)
         .listen(listener)
         .unwrap()
         .run();
@@ -212,7 +636,7 @@ mod tests {
         let handle = server.handle();
         actix_web::rt::spawn(server);
 
-        (format!("http://{addr}/webhook"), received, handle)
+        (format!("http://{addr}/webhook"), received, attempts, handle)
     }
 
     #[actix_web::test]
@@ -220,10 +644,7 @@ mod tests {
         let (downstream_url, received, handle) = start_downstream().await;
 
         let config = test_config(downstream_url);
-        let app_state = web::Data::new(AppState {
-            config,
-            client: reqwest::Client::new(),
-        });
+        let app_state = web::Data::new(AppState::new(config).unwrap());
 
         let app = test::init_service(
             App::new()
@@ -239,8 +660,7 @@ mod tests {
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
 
-        let forwarded = received.lock().unwrap();
-        assert_eq!(forwarded.as_slice(), &["play music"]);
+        assert_eq!(forwarded_texts(&received), ["play music"]);
 
         handle.stop(true).await;
     }
@@ -249,10 +669,7 @@ mod tests {
     async fn ignores_empty_command_after_activation_word() {
         let (downstream_url, _received, handle) = start_downstream().await;
         let config = test_config(downstream_url);
-        let app_state = web::Data::new(AppState {
-            config,
-            client: reqwest::Client::new(),
-        });
+        let app_state = web::Data::new(AppState::new(config).unwrap());
         let app = test::init_service(
             App::new()
                 .app_data(app_state)
@@ -274,11 +691,9 @@ mod tests {
     #[actix_web::test]
     async fn stops_on_stop_word_and_does_not_forward() {
         let (downstream_url, received, handle) = start_downstream().await;
-        let config = test_config(downstream_url);
-        let app_state = web::Data::new(AppState {
-            config,
-            client: reqwest::Client::new(),
-        });
+        let mut config = test_config(downstream_url);
+        config.stop_word_policy = StopWordPolicy::Start;
+        let app_state = web::Data::new(AppState::new(config).unwrap());
         let app = test::init_service(
             App::new()
                 .app_data(app_state)
@@ -294,8 +709,958 @@ mod tests {
         assert_eq!(resp["status"], "stopped");
         assert!(resp["command"].is_null());
 
-        let forwarded = received.lock().unwrap();
-        assert!(forwarded.is_empty());
+        assert!(forwarded_texts(&received).is_empty());
+        assert_eq!(forwarded_events(&received), ["cancel"]);
+
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn forwards_command_that_only_starts_with_stop_word() {
+        let (downstream_url, received, handle) = start_downstream().await;
+        let config = test_config(downstream_url);
+        let app_state = web::Data::new(AppState::new(config).unwrap());
+        let app = test::init_service(App::new().app_data(app_state).service(webhook)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/webhook")
+            .set_json(serde_json::json!({ "text": "va stop the music" }))
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["status"], "accepted");
+        assert_eq!(resp["command"], "stop the music");
+
+        let req = test::TestRequest::post()
+            .uri("/webhook")
+            .set_json(serde_json::json!({ "text": "va stop" }))
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["status"], "stopped");
+
+        assert_eq!(forwarded_events(&received), ["command", "cancel"]);
+        assert_eq!(forwarded_texts(&received), ["stop the music"]);
+
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn forwards_command_after_longest_activation_word() {
+        let (downstream_url, received, handle) = start_downstream().await;
+        let mut config = test_config(downstream_url);
+        config
+            .rules
+            .activation_words
+            .insert("va assistant".to_string());
+        let app_state = web::Data::new(AppState::new(config).unwrap());
+        let app = test::init_service(App::new().app_data(app_state).service(webhook)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/webhook")
+            .set_json(serde_json::json!({ "text": "VA  Assistant play music" }))
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["status"], "accepted");
+        assert_eq!(resp["command"], "play music");
+
+        assert_eq!(forwarded_texts(&received), ["play music"]);
+
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn routes_command_by_activation_word() {
+        let (default_url, default_received, default_handle) = start_downstream().await;
+        let (home_url, home_received, home_handle) = start_downstream().await;
+        let mut config = test_config(default_url);
+        config.rules = Rules::parse(
+            &format!(
+                r#"
+                [[routes]]
+                name = "home"
+                activation_word = "computer"
+                url = "{home_url}"
+                "#
+            ),
+            &config.rule_defaults,
+        )
+        .unwrap();
+        config.rules.activation_words.insert("computer".to_string());
+        let app_state = web::Data::new(AppState::new(config).unwrap());
+        let app = test::init_service(App::new().app_data(app_state).service(webhook)).await;
+
+        for text in ["computer lights off", "va play music"] {
+            let req = test::TestRequest::post()
+                .uri("/webhook")
+                .set_json(serde_json::json!({ "text": text }))
+                .to_request();
+            let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(resp["status"], "accepted");
+        }
+
+        assert_eq!(forwarded_texts(&home_received), ["lights off"]);
+        assert_eq!(forwarded_texts(&default_received), ["play music"]);
+
+        default_handle.stop(true).await;
+        home_handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn retries_unavailable_downstream() {
+        let (downstream_url, received, attempts, handle) = start_failing_downstream(2).await;
+        let mut config = test_config(downstream_url);
+        config.forward.max_retries = 2;
+        let app_state = web::Data::new(AppState::new(config).unwrap());
+        let app = test::init_service(App::new().app_data(app_state).service(webhook)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/webhook")
+            .set_json(serde_json::json!({ "text": "va play music" }))
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["status"], "accepted");
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        assert_eq!(forwarded_texts(&received), ["play music"]);
+
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn open_breaker_answers_downstream_unavailable() {
+        let (downstream_url, received, attempts, handle) =
+            start_failing_downstream(usize::MAX).await;
+        let mut config = test_config(downstream_url);
+        config.forward.max_retries = 1;
+        config.forward.breaker_threshold = 1;
+        let app_state = web::Data::new(AppState::new(config).unwrap());
+        let app = test::init_service(App::new().app_data(app_state).service(webhook)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/webhook")
+            .set_json(serde_json::json!({ "text": "va play music" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_GATEWAY);
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+
+        let req = test::TestRequest::post()
+            .uri("/webhook")
+            .set_json(serde_json::json!({ "text": "va play music" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.status(),
+            actix_web::http::StatusCode::SERVICE_UNAVAILABLE
+        );
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["status"], "downstream_unavailable");
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        assert!(received.lock().unwrap().is_empty());
+
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn breaker_lets_trial_request_through_after_cooldown() {
+        let (downstream_url, received, attempts, handle) = start_failing_downstream(1).await;
+        let mut config = test_config(downstream_url);
+        config.forward.breaker_threshold = 1;
+        config.forward.breaker_cooldown = Duration::from_millis(50);
+        let app_state = web::Data::new(AppState::new(config).unwrap());
+        let app = test::init_service(App::new().app_data(app_state).service(webhook)).await;
+
+        let statuses = [
+            (0, actix_web::http::StatusCode::BAD_GATEWAY),
+            (0, actix_web::http::StatusCode::SERVICE_UNAVAILABLE),
+            (60, actix_web::http::StatusCode::OK),
+        ];
+        for (delay_ms, expected) in statuses {
+            actix_web::rt::time::sleep(Duration::from_millis(delay_ms)).await;
+            let req = test::TestRequest::post()
+                .uri("/webhook")
+                .set_json(serde_json::json!({ "text": "va play music" }))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), expected);
+        }
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        assert_eq!(forwarded_texts(&received), ["play music"]);
+
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn forwards_metadata_with_command() {
+        let (downstream_url, received, handle) = start_downstream().await;
+        let config = test_config(downstream_url);
+        let app_state = web::Data::new(AppState::new(config).unwrap());
+        let app = test::init_service(App::new().app_data(app_state).service(webhook)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/webhook")
+            .insert_header((REQUEST_ID_HEADER, "req-1"))
+            .set_json(serde_json::json!({
+                "text": "  Assistant Play music ",
+                "source": "kitchen",
+                "speaker": "alice"
+            }))
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["status"], "accepted");
+
+        let req = test::TestRequest::post()
+            .uri("/webhook")
+            .set_json(serde_json::json!({ "text": "va stop" }))
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["status"], "stopped");
+
+        let forwarded = received.lock().unwrap().clone();
+        let command = &forwarded[0];
+        assert_eq!(command["event"], "command");
+        assert_eq!(command["text"], "play music");
+        assert_eq!(command["original_text"], "  Assistant Play music ");
+        assert_eq!(command["activation_word"], "assistant");
+        assert_eq!(command["request_id"], "req-1");
+        assert_eq!(command[REQUEST_ID_HEADER], "req-1");
+        assert_eq!(command["source"], "kitchen");
+        assert_eq!(command["speaker"], "alice");
+        assert!(
+            chrono::DateTime::parse_from_rfc3339(command["received_at"].as_str().unwrap()).is_ok()
+        );
+
+        let cancel = &forwarded[1];
+        assert_eq!(cancel["event"], "cancel");
+        assert!(cancel.get("text").is_none());
+        assert!(cancel.get("source").is_none());
+        let request_id = cancel["request_id"].as_str().unwrap();
+        assert!(uuid::Uuid::parse_str(request_id).is_ok());
+        assert_eq!(cancel[REQUEST_ID_HEADER], request_id);
+
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn forwards_matched_intent_and_slots() {
+        let (downstream_url, received, handle) = start_downstream().await;
+        let mut config = test_config(downstream_url);
+        config.rules = Rules::parse(
+            r#"
+            [[intents]]
+            name = "set_timer"
+            pattern = "set (a )?timer for {duration}"
+            "#,
+            &config.rule_defaults,
+        )
+        .unwrap();
+        let app_state = web::Data::new(AppState::new(config).unwrap());
+        let app = test::init_service(App::new().app_data(app_state).service(webhook)).await;
+
+        for text in ["va set a timer for ten minutes", "va play music"] {
+            let req = test::TestRequest::post()
+                .uri("/webhook")
+                .set_json(serde_json::json!({ "text": text }))
+                .to_request();
+            let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(resp["status"], "accepted");
+        }
+
+        let forwarded = received.lock().unwrap().clone();
+        assert_eq!(forwarded[0]["text"], "set a timer for ten minutes");
+        assert_eq!(forwarded[0]["intent"], "set_timer");
+        assert_eq!(
+            forwarded[0]["slots"],
+            serde_json::json!({ "duration": "ten minutes" })
+        );
+        assert!(forwarded[1].get("intent").is_none());
+        assert!(forwarded[1].get("slots").is_none());
+
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn suppresses_duplicate_utterances() {
+        let (downstream_url, received, handle) = start_downstream().await;
+        let mut config = test_config(downstream_url);
+        config.dedup_ttl = Duration::from_millis(100);
+        let app_state = web::Data::new(AppState::new(config).unwrap());
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state)
+                .service(webhook)
+                .service(metrics_snapshot),
+        )
+        .await;
+
+        let utterances = [
+            (0, "va turn off the lights", Some("kitchen"), "accepted"),
+            (0, "VA  turn off the lights", Some("kitchen"), "duplicate"),
+            (0, "va turn off the lights", Some("office"), "accepted"),
+            (150, "va turn off the lights", Some("kitchen"), "accepted"),
+            (0, "va stop", Some("kitchen"), "stopped"),
+            (0, "va stop", Some("kitchen"), "stopped"),
+        ];
+        for (delay_ms, text, source, expected) in utterances {
+            actix_web::rt::time::sleep(Duration::from_millis(delay_ms)).await;
+            let req = test::TestRequest::post()
+                .uri("/webhook")
+                .set_json(serde_json::json!({ "text": text, "source": source }))
+                .to_request();
+            let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(resp["status"], expected, "{text:?} from {source:?}");
+        }
+        assert_eq!(forwarded_texts(&received).len(), 3);
+        let cancels = forwarded_events(&received)
+            .into_iter()
+            .filter(|event| event == "cancel")
+            .count();
+        assert_eq!(cancels, 2);
+
+        let req = test::TestRequest::get().uri("/metrics").to_request();
+        let metrics: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(metrics["duplicates"], 1);
+
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn does_not_suppress_retry_of_failed_command() {
+        let (downstream_url, received, _attempts, handle) = start_failing_downstream(1).await;
+        let mut config = test_config(downstream_url);
+        config.dedup_ttl = Duration::from_secs(60);
+        let app_state = web::Data::new(AppState::new(config).unwrap());
+        let app = test::init_service(App::new().app_data(app_state).service(webhook)).await;
+
+        for expected in ["error", "accepted", "duplicate"] {
+            let req = test::TestRequest::post()
+                .uri("/webhook")
+                .set_json(serde_json::json!({ "text": "va lights off" }))
+                .to_request();
+            let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(resp["status"], expected);
+        }
+        assert_eq!(forwarded_texts(&received), ["lights off"]);
+
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn listening_window_takes_next_utterance_as_command() {
+        let (downstream_url, received, handle) = start_downstream().await;
+        let mut config = test_config(downstream_url);
+        config.listen_window = Duration::from_secs(5);
+        let app_state = web::Data::new(AppState::new(config).unwrap());
+        let app = test::init_service(App::new().app_data(app_state).service(webhook)).await;
+
+        let utterances = [
+            ("va", "kitchen", "listening"),
+            ("play music", "office", "ignored"),
+            ("play music", "kitchen", "accepted"),
+            ("play jazz", "kitchen", "ignored"),
+        ];
+        for (text, source, expected) in utterances {
+            let req = test::TestRequest::post()
+                .uri("/webhook")
+                .set_json(serde_json::json!({ "text": text, "source": source }))
+                .to_request();
+            let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(resp["status"], expected, "{text:?} from {source}");
+        }
+
+        let forwarded = received.lock().unwrap().clone();
+        assert_eq!(forwarded.len(), 1);
+        assert_eq!(forwarded[0]["text"], "play music");
+        assert_eq!(forwarded[0]["activation_word"], "va");
+        assert_eq!(forwarded[0]["source"], "kitchen");
+
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn applies_per_source_activation_words() {
+        let (downstream_url, received, handle) = start_downstream().await;
+        let mut config = test_config(downstream_url);
+        config.rules = Rules::parse(
+            r#"
+            [sources.kitchen]
+            activation_words = ["computer"]
+            "#,
+            &config.rule_defaults,
+        )
+        .unwrap();
+        let app_state = web::Data::new(AppState::new(config).unwrap());
+        let app = test::init_service(App::new().app_data(app_state).service(webhook)).await;
+
+        let utterances = [
+            ("va play music", Some("kitchen"), "ignored"),
+            ("computer play music", Some("kitchen"), "accepted"),
+            ("computer play jazz", Some("office"), "ignored"),
+            ("va play jazz", Some("office"), "accepted"),
+            ("va play blues", None, "accepted"),
+        ];
+        for (text, source, expected) in utterances {
+            let req = test::TestRequest::post()
+                .uri("/webhook")
+                .set_json(serde_json::json!({ "text": text, "source": source }))
+                .to_request();
+            let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(resp["status"], expected, "{text:?} from {source:?}");
+        }
+        assert_eq!(
+            forwarded_texts(&received),
+            ["play music", "play jazz", "play blues"]
+        );
+
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn reloaded_rules_apply_to_next_request() {
+        let (downstream_url, received, handle) = start_downstream().await;
+        let path =
+            std::env::temp_dir().join(format!("va-activator-{}-webhook.toml", std::process::id()));
+        std::fs::write(&path, r#"activation_words = ["va"]"#).unwrap();
+        let mut config = test_config(downstream_url);
+        config.rules = Rules::load(&path, &config.rule_defaults).unwrap();
+        config.rules_file = Some(path.clone());
+        config.admin_token = Some("secret".to_string());
+        let app_state = web::Data::new(AppState::new(config).unwrap());
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state.clone())
+                .service(webhook)
+                .service(active_config),
+        )
+        .await;
+
+        std::fs::write(&path, r#"activation_words = ["computer"]"#).unwrap();
+        app_state.rules.reload().unwrap();
+
+        let req = test::TestRequest::get().uri("/config").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 401);
+        let req = test::TestRequest::get()
+            .uri("/config")
+            .insert_header(("Authorization", "Bearer secret"))
+            .to_request();
+        let active: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(active["activation_words"], serde_json::json!(["computer"]));
+        assert_eq!(active["stop_words"], serde_json::json!(["cancel", "stop"]));
+
+        for (text, expected) in [
+            ("va play music", "ignored"),
+            ("computer play music", "accepted"),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/webhook")
+                .set_json(serde_json::json!({ "text": text }))
+                .to_request();
+            let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(resp["status"], expected);
+        }
+        assert_eq!(forwarded_texts(&received), ["play music"]);
+
+        std::fs::remove_file(path).unwrap();
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn rejects_activation_word_with_low_confidence() {
+        let (downstream_url, received, handle) = start_downstream().await;
+        let mut config = test_config(downstream_url);
+        config.min_activation_confidence = 0.6;
+        let app_state = web::Data::new(AppState::new(config).unwrap());
+        let app = test::init_service(App::new().app_data(app_state.clone()).service(webhook)).await;
+
+        let cases = [
+            (
+                serde_json::json!([
+                    { "word": "va", "conf": 0.4, "start": 0.0, "end": 0.3 },
+                    { "word": "play", "conf": 0.9, "start": 0.3, "end": 0.6 },
+                    { "word": "music", "conf": 0.9, "start": 0.6, "end": 1.0 },
+                ]),
+                "low_confidence",
+            ),
+            (
+                serde_json::json!([
+                    { "word": "va", "conf": 0.8 },
+                    { "word": "play", "conf": 0.3 },
+                    { "word": "music", "conf": 0.9 },
+                ]),
+                "accepted",
+            ),
+            (serde_json::json!([]), "accepted"),
+        ];
+        for (words, expected) in cases {
+            let req = test::TestRequest::post()
+                .uri("/webhook")
+                .set_json(serde_json::json!({ "text": "va play music", "words": words }))
+                .to_request();
+            let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(resp["status"], expected);
+        }
+
+        assert_eq!(forwarded_texts(&received), ["play music", "play music"]);
+        assert_eq!(app_state.metrics.low_confidence.load(Ordering::Relaxed), 1);
+
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn rejects_requests_without_valid_secret() {
+        let (downstream_url, received, handle) = start_downstream().await;
+        let mut config = test_config(downstream_url);
+        config.webhook_auth = WebhookAuthMode::Secret;
+        config.webhook_secret = Some("s3cret".to_string());
+        let app_state = web::Data::new(AppState::new(config).unwrap());
+        let app = test::init_service(App::new().app_data(app_state.clone()).service(webhook)).await;
+
+        for secret in [None, Some("wrong"), Some("s3cret")] {
+            let mut req = test::TestRequest::post()
+                .uri("/webhook")
+                .set_json(serde_json::json!({ "text": "va play music" }));
+            if let Some(secret) = secret {
+                req = req.insert_header(("X-Webhook-Secret", secret));
+            }
+            let resp = test::call_service(&app, req.to_request()).await;
+            let expected = if secret == Some("s3cret") { 200 } else { 401 };
+            assert_eq!(resp.status(), expected);
+        }
+
+        assert_eq!(forwarded_texts(&received), ["play music"]);
+        assert_eq!(app_state.metrics.unauthorized.load(Ordering::Relaxed), 2);
+
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn rate_limits_requests_per_source() {
+        let (downstream_url, received, handle) = start_downstream().await;
+        let mut config = test_config(downstream_url);
+        config.rate_limit_rps = 0.001;
+        config.rate_limit_burst = 2;
+        config.rate_limit_key = RateLimitKey::Source;
+        let app_state = web::Data::new(AppState::new(config).unwrap());
+        let app = test::init_service(App::new().app_data(app_state.clone()).service(webhook)).await;
+
+        let mut statuses = Vec::new();
+        for (text, source) in [
+            ("va one", "kitchen"),
+            ("va two", "kitchen"),
+            ("va three", "kitchen"),
+            ("va four", "office"),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/webhook")
+                .set_json(serde_json::json!({ "text": text, "source": source }))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            statuses.push(resp.status().as_u16());
+            if resp.status() == 429 {
+                assert!(resp.headers().contains_key("retry-after"));
+            }
+        }
+
+        assert_eq!(statuses, [200, 200, 429, 200]);
+        assert_eq!(forwarded_texts(&received), ["one", "two", "four"]);
+        assert_eq!(app_state.metrics.rate_limited.load(Ordering::Relaxed), 1);
+
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn rate_limits_rejected_requests_by_address() {
+        let (downstream_url, received, handle) = start_downstream().await;
+        for key in [RateLimitKey::Ip, RateLimitKey::Source] {
+            let mut config = test_config(downstream_url.clone());
+            config.webhook_auth = WebhookAuthMode::Secret;
+            config.webhook_secret = Some("s3cret".to_string());
+            config.rate_limit_rps = 0.001;
+            config.rate_limit_burst = 2;
+            config.rate_limit_key = key;
+            let app_state = web::Data::new(AppState::new(config).unwrap());
+            let app = test::init_service(App::new().app_data(app_state).service(webhook)).await;
+
+            let mut statuses = Vec::new();
+            for (peer, secret, body) in [
+                ("10.0.0.1:4000", "wrong", r#"{"text": "va one"}"#),
+                ("10.0.0.1:4001", "s3cret", "not json"),
+                ("10.0.0.1:4002", "wrong", r#"{"text": "va one"}"#),
+                ("10.0.0.2:4000", "s3cret", r#"{"text": "va two"}"#),
+            ] {
+                let req = test::TestRequest::post()
+                    .uri("/webhook")
+                    .peer_addr(peer.parse().unwrap())
+                    .insert_header(("X-Webhook-Secret", secret))
+                    .insert_header(("Content-Type", "application/json"))
+                    .set_payload(body)
+                    .to_request();
+                statuses.push(test::call_service(&app, req).await.status().as_u16());
+            }
+            assert_eq!(statuses, [401, 400, 429, 200], "{key:?}");
+        }
+        assert_eq!(forwarded_texts(&received), ["two", "two"]);
+
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn streams_decisions_to_event_subscribers() {
+        let (downstream_url, _received, handle) = start_downstream().await;
+        let mut config = test_config(downstream_url);
+        config.listen_window = Duration::from_secs(10);
+        config.admin_token = Some("secret".to_string());
+        let app_state = web::Data::new(AppState::new(config).unwrap());
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state.clone())
+                .service(webhook)
+                .service(event_stream),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/events").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 401);
+        let req = test::TestRequest::get()
+            .uri("/events")
+            .insert_header(("Authorization", "Bearer secret"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.headers().get("content-type").unwrap(),
+            "text/event-stream"
+        );
+        let mut body = Box::pin(resp.into_body());
+
+        for (text, source) in [
+            ("hello", None),
+            ("va", Some("kitchen")),
+            ("turn on the lights", Some("kitchen")),
+            ("va stop", None),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/webhook")
+                .insert_header(("X-Request-Id", text))
+                .set_json(serde_json::json!({ "text": text, "source": source }))
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+
+        let mut events = Vec::new();
+        for _ in 0..4 {
+            let frame = std::future::poll_fn(|cx| body.as_mut().poll_next(cx))
+                .await
+                .unwrap()
+                .unwrap();
+            let frame = std::str::from_utf8(&frame).unwrap();
+            let json = frame.strip_prefix("data: ").unwrap().trim_end();
+            events.push(serde_json::from_str::<serde_json::Value>(json).unwrap());
+        }
+
+        let statuses = events
+            .iter()
+            .map(|event| event["status"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(statuses, ["ignored", "listening", "accepted", "stopped"]);
+        assert_eq!(events[1]["activation_word"], "va");
+        assert_eq!(events[2]["command"], "turn on the lights");
+        assert_eq!(events[2]["source"], "kitchen");
+        assert_eq!(events[2]["request_id"], "turn on the lights");
+        assert!(events[0].get("activation_word").is_none());
+        assert!(events[3]["received_at"].is_string());
+
+        handle.stop(true).await;
+    }
+
+    fn admin_request(
+        method: actix_web::http::Method,
+        list: &str,
+        token: &str,
+    ) -> test::TestRequest {
+        test::TestRequest::default()
+            .method(method)
+            .uri(&format!("/admin/{list}"))
+            .insert_header(("Authorization", format!("Bearer {token}")))
+    }
+
+    #[actix_web::test]
+    async fn admin_api_requires_token() {
+        let mut config = test_config("http://127.0.0.1:9/webhook".to_string());
+        config.admin_token = Some("secret".to_string());
+        let app_state = web::Data::new(AppState::new(config).unwrap());
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state.clone())
+                .service(admin::get_words),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/admin/activation-words")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 401);
+        let req = admin_request(Method::GET, "activation-words", "wrong").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 401);
+        let req = admin_request(Method::GET, "other-words", "secret").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+        let req = admin_request(Method::GET, "stop-words", "secret").to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["words"], serde_json::json!(["cancel", "stop"]));
+    }
+
+    #[actix_web::test]
+    async fn admin_api_is_disabled_without_token() {
+        let app_state = web::Data::new(
+            AppState::new(test_config("http://127.0.0.1:9/webhook".to_string())).unwrap(),
+        );
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state.clone())
+                .service(admin::get_words),
+        )
+        .await;
+
+        let req = admin_request(Method::GET, "activation-words", "").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn admin_api_replaces_activation_words() {
+        let (downstream_url, received, handle) = start_downstream().await;
+        let mut config = test_config(downstream_url);
+        config.admin_token = Some("secret".to_string());
+        let app_state = web::Data::new(AppState::new(config).unwrap());
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state.clone())
+                .service(webhook)
+                .service(admin::replace_words)
+                .service(admin::remove_words),
+        )
+        .await;
+
+        let req = admin_request(Method::PUT, "activation-words", "secret")
+            .set_json(serde_json::json!({ "words": ["Computer", "hey  VA"] }))
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["words"], serde_json::json!(["computer", "hey va"]));
+
+        let req = admin_request(Method::DELETE, "activation-words", "secret")
+            .set_json(serde_json::json!({ "words": ["hey va"] }))
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["words"], serde_json::json!(["computer"]));
+
+        for (text, expected) in [
+            ("va play music", "ignored"),
+            ("computer play music", "accepted"),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/webhook")
+                .set_json(serde_json::json!({ "text": text }))
+                .to_request();
+            let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(resp["status"], expected);
+        }
+        assert_eq!(forwarded_texts(&received), ["play music"]);
+
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn admin_api_rejects_invalid_word_lists() {
+        let mut config = test_config("http://127.0.0.1:9/webhook".to_string());
+        config.admin_token = Some("secret".to_string());
+        let app_state = web::Data::new(AppState::new(config).unwrap());
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state.clone())
+                .service(admin::replace_words)
+                .service(admin::remove_words),
+        )
+        .await;
+
+        let invalid = [
+            (
+                Method::PUT,
+                "activation-words",
+                serde_json::json!({ "words": ["va", "Stop"] }),
+            ),
+            (
+                Method::PUT,
+                "stop-words",
+                serde_json::json!({ "words": [] }),
+            ),
+            (
+                Method::PUT,
+                "stop-words",
+                serde_json::json!({ "words": ["  "] }),
+            ),
+            (
+                Method::DELETE,
+                "stop-words",
+                serde_json::json!({ "words": ["stop", "cancel"] }),
+            ),
+        ];
+        for (method, list, body) in invalid {
+            let req = admin_request(method, list, "secret")
+                .set_json(body)
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 400);
+        }
+
+        let rules = app_state.rules.current();
+        assert_eq!(rules.activation_words.len(), 2);
+        assert_eq!(rules.stop_words.len(), 2);
+    }
+
+    async fn wait_for_forwarded(received: &Received, count: usize) {
+        for _ in 0..100 {
+            if received.lock().unwrap().len() >= count {
+                return;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("downstream did not receive {count} requests");
+    }
+
+    #[actix_web::test]
+    async fn async_mode_accepts_and_forwards_in_order() {
+        let (downstream_url, received, handle) = start_downstream().await;
+        let mut config = test_config(downstream_url);
+        config.forward_mode = ForwardMode::Async;
+        let app_state = web::Data::new(AppState::new(config).unwrap());
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state)
+                .service(webhook)
+                .service(metrics_snapshot),
+        )
+        .await;
+
+        for text in ["va one", "va two", "va three"] {
+            let req = test::TestRequest::post()
+                .uri("/webhook")
+                .set_json(serde_json::json!({ "text": text, "source": "kitchen" }))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), actix_web::http::StatusCode::ACCEPTED);
+            let body: serde_json::Value = test::read_body_json(resp).await;
+            assert_eq!(body["status"], "accepted");
+        }
+
+        wait_for_forwarded(&received, 3).await;
+        assert_eq!(forwarded_texts(&received), ["one", "two", "three"]);
+
+        let mut metrics = serde_json::Value::Null;
+        for _ in 0..100 {
+            let req = test::TestRequest::get().uri("/metrics").to_request();
+            metrics = test::call_and_read_body_json(&app, req).await;
+            if metrics["queue_depth"] == 0 {
+                break;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(metrics["queue_depth"], 0);
+        assert_eq!(metrics["queued"], 3);
+        assert_eq!(metrics["forwarded"], 3);
+
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn async_mode_drops_commands_when_queue_is_full() {
+        let (downstream_url, received, _attempts, handle) = start_failing_downstream(1).await;
+        let mut config = test_config(downstream_url);
+        config.forward_mode = ForwardMode::Async;
+        config.queue_capacity = 1;
+        config.forward.max_retries = 1;
+        config.forward.retry_backoff = Duration::from_millis(200);
+        let app_state = web::Data::new(AppState::new(config).unwrap());
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state)
+                .service(webhook)
+                .service(metrics_snapshot),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/webhook")
+            .set_json(serde_json::json!({ "text": "va one" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::ACCEPTED);
+
+        let req = test::TestRequest::post()
+            .uri("/webhook")
+            .set_json(serde_json::json!({ "text": "va two" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.status(),
+            actix_web::http::StatusCode::SERVICE_UNAVAILABLE
+        );
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["status"], "queue_full");
+
+        wait_for_forwarded(&received, 1).await;
+        assert_eq!(forwarded_texts(&received), ["one"]);
+
+        let req = test::TestRequest::get().uri("/metrics").to_request();
+        let metrics: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(metrics["dropped"], 1);
+        assert_eq!(metrics["queue_capacity"], 1);
+
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn async_mode_sends_cancels_ahead_of_queued_commands() {
+        let (downstream_url, received, attempts, handle) = start_failing_downstream(1).await;
+        let mut config = test_config(downstream_url);
+        config.forward_mode = ForwardMode::Async;
+        config.forward.max_retries = 1;
+        config.forward.retry_backoff = Duration::from_millis(300);
+        let app_state = web::Data::new(AppState::new(config).unwrap());
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state)
+                .service(webhook)
+                .service(metrics_snapshot),
+        )
+        .await;
+
+        let send = |text: &str| {
+            test::TestRequest::post()
+                .uri("/webhook")
+                .set_json(serde_json::json!({ "text": text, "source": "kitchen" }))
+                .to_request()
+        };
+        test::call_service(&app, send("va one")).await;
+        // The first attempt fails, so "one" is waiting for its retry.
+        for _ in 0..100 {
+            if attempts.load(Ordering::SeqCst) > 0 {
+                break;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+        }
+        test::call_service(&app, send("va two")).await;
+        let resp = test::call_service(&app, send("va stop")).await;
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["status"], "stopped");
+
+        wait_for_forwarded(&received, 2).await;
+        for _ in 0..100 {
+            let req = test::TestRequest::get().uri("/metrics").to_request();
+            let metrics: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+            if metrics["queue_depth"] == 0 {
+                break;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(forwarded_events(&received), ["cancel", "command"]);
+        assert_eq!(forwarded_texts(&received), ["one"]);
 
         handle.stop(true).await;
     }
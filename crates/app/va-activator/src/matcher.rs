@@ -0,0 +1,348 @@
+use std::collections::HashSet;
+
+use serde::Deserialize;
+
+use crate::config::StopWordPolicy;
+use crate::rules::Rules;
+
+/// One word of the recognizer's result, as reported by Vosk.
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct RecognizedWord {
+    pub(crate) word: String,
+    pub(crate) conf: f32,
+}
+
+/// Settings that decide how an utterance is matched, independent of the word lists.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct MatchOptions {
+    pub(crate) stop_word_policy: StopWordPolicy,
+    /// Lowest recognizer confidence at which an activation word is accepted; zero accepts any
+    /// confidence.
+    pub(crate) min_activation_confidence: f32,
+}
+
+/// What the word lists say about one utterance. Per-source state (listening windows, duplicates)
+/// and delivery are up to the caller.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Match {
+    /// The text does not start with an activation word.
+    NoActivation,
+    /// The activation word was recognized with a confidence below the threshold.
+    LowConfidence {
+        activation_word: String,
+        confidence: f32,
+    },
+    /// Nothing followed the activation word.
+    ActivationOnly { activation_word: String },
+    Stop {
+        activation_word: String,
+        command: String,
+    },
+    Command {
+        activation_word: String,
+        command: String,
+    },
+}
+
+/// Matches utterances against the word lists that apply to one source.
+pub(crate) struct Matcher<'a> {
+    activation_words: &'a HashSet<String>,
+    stop_words: &'a HashSet<String>,
+    options: MatchOptions,
+}
+
+impl<'a> Matcher<'a> {
+    /// Uses the source's word lists from `[sources.<name>]` where the rules define them, the global
+    /// lists otherwise.
+    pub(crate) fn new(rules: &'a Rules, source: Option<&str>, options: MatchOptions) -> Self {
+        let source_rules = rules.source(source);
+        Self {
+            activation_words: source_rules
+                .and_then(|source_rules| source_rules.activation_words.as_ref())
+                .unwrap_or(&rules.activation_words),
+            stop_words: source_rules
+                .and_then(|source_rules| source_rules.stop_words.as_ref())
+                .unwrap_or(&rules.stop_words),
+            options,
+        }
+    }
+
+    /// Matches a normalized utterance. `words` are the recognizer's per-word results, if the caller
+    /// has them.
+    pub(crate) fn match_utterance(&self, text: &str, words: &[RecognizedWord]) -> Match {
+        let Some(activation_word) = find_activation_word(text, self.activation_words) else {
+            return Match::NoActivation;
+        };
+        if let Some(confidence) = activation_confidence(activation_word, words) {
+            if confidence < self.options.min_activation_confidence {
+                return Match::LowConfidence {
+                    activation_word: activation_word.to_string(),
+                    confidence,
+                };
+            }
+        }
+        self.match_command(activation_word, text[activation_word.len()..].trim())
+    }
+
+    /// Classifies what was said after `activation_word`, in the same utterance or, with a listening
+    /// window, in the next one.
+    pub(crate) fn match_command(&self, activation_word: &str, command: &str) -> Match {
+        let activation_word = activation_word.to_string();
+        if command.is_empty() {
+            Match::ActivationOnly { activation_word }
+        } else if is_stop_command(command, self.stop_words, self.options.stop_word_policy) {
+            Match::Stop {
+                activation_word,
+                command: command.to_string(),
+            }
+        } else {
+            Match::Command {
+                activation_word,
+                command: command.to_string(),
+            }
+        }
+    }
+}
+
+pub(crate) fn normalize(input: &str) -> String {
+    input
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Confidence of the activation word: the lowest confidence among the recognized words that make up
+/// the phrase.
+///
+/// `None` when the request has no word confidences. Confidences that do not line up with the
+/// activation word count as zero, so a transcript that disagrees with its own words is not accepted
+/// unchecked.
+fn activation_confidence(activation_word: &str, words: &[RecognizedWord]) -> Option<f32> {
+    if words.is_empty() {
+        return None;
+    }
+    let mut recognized = words.iter();
+    let mut confidence = f32::INFINITY;
+    for expected in activation_word.split(' ') {
+        match recognized.next() {
+            Some(word) if normalize(&word.word) == expected => {
+                confidence = confidence.min(word.conf)
+            }
+            _ => return Some(0.0),
+        }
+    }
+    Some(confidence)
+}
+
+/// Finds the activation word the text starts with.
+///
+/// When several activation words match (e.g. `va` and `va assistant`), the longest one wins.
+/// Matches of equal length are resolved lexicographically, so the result never depends on the order
+/// of the words.
+fn find_activation_word<'a>(
+    text: &str,
+    words: impl IntoIterator<Item = &'a String>,
+) -> Option<&'a str> {
+    words
+        .into_iter()
+        .filter(|word| starts_with_phrase(text, word))
+        .max_by(|a, b| a.len().cmp(&b.len()).then_with(|| b.cmp(a)))
+        .map(String::as_str)
+}
+
+pub(crate) fn starts_with_phrase(text: &str, phrase: &str) -> bool {
+    text.starts_with(phrase) && matches!(text.as_bytes().get(phrase.len()), None | Some(&b' '))
+}
+
+/// Checks whether the command is a stop request under the given policy.
+fn is_stop_command(text: &str, stop_words: &HashSet<String>, policy: StopWordPolicy) -> bool {
+    let starts_with_stop_word = |text: &str| {
+        stop_words
+            .iter()
+            .filter(|word| starts_with_phrase(text, word))
+            .map(String::len)
+            .max()
+    };
+
+    match policy {
+        StopWordPolicy::Only => {
+            let mut rest = text;
+            while let Some(len) = starts_with_stop_word(rest) {
+                rest = rest[len..].trim_start();
+                if rest.is_empty() {
+                    return true;
+                }
+            }
+            false
+        }
+        StopWordPolicy::Start => starts_with_stop_word(text).is_some(),
+        StopWordPolicy::Anywhere => std::iter::once(0)
+            .chain(text.match_indices(' ').map(|(index, _)| index + 1))
+            .any(|index| starts_with_stop_word(&text[index..]).is_some()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::RuleDefaults;
+    use proptest::prelude::*;
+
+    fn set(words: &[&str]) -> HashSet<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    fn recognized(words: &[(&str, f32)]) -> Vec<RecognizedWord> {
+        words
+            .iter()
+            .map(|&(word, conf)| RecognizedWord {
+                word: word.to_string(),
+                conf,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_utterances() {
+        let rules = Rules::from_defaults(&RuleDefaults {
+            webhook_url: Some("http://127.0.0.1:8092/webhook".to_string()),
+            activation_words: Some(set(&["va", "va assistant"])),
+            stop_words: Some(set(&["stop"])),
+        })
+        .unwrap();
+        let options = MatchOptions {
+            stop_word_policy: StopWordPolicy::Only,
+            min_activation_confidence: 0.5,
+        };
+        let matcher = Matcher::new(&rules, None, options);
+
+        assert_eq!(
+            matcher.match_utterance("play music", &[]),
+            Match::NoActivation
+        );
+        assert_eq!(
+            matcher.match_utterance("va assistant", &[]),
+            Match::ActivationOnly {
+                activation_word: "va assistant".to_string()
+            }
+        );
+        assert_eq!(
+            matcher.match_utterance("va stop", &[]),
+            Match::Stop {
+                activation_word: "va".to_string(),
+                command: "stop".to_string()
+            }
+        );
+        assert_eq!(
+            matcher.match_utterance("va play music", &recognized(&[("va", 0.9)])),
+            Match::Command {
+                activation_word: "va".to_string(),
+                command: "play music".to_string()
+            }
+        );
+        assert_eq!(
+            matcher.match_utterance("va play music", &recognized(&[("va", 0.25)])),
+            Match::LowConfidence {
+                activation_word: "va".to_string(),
+                confidence: 0.25
+            }
+        );
+        assert_eq!(
+            matcher.match_utterance("va play music", &recognized(&[("the", 0.9)])),
+            Match::LowConfidence {
+                activation_word: "va".to_string(),
+                confidence: 0.0
+            }
+        );
+    }
+
+    #[test]
+    fn applies_stop_word_policy() {
+        let words = set(&["stop", "cancel", "never mind"]);
+        let cases = [
+            ("stop", [true, true, true]),
+            ("never mind stop", [true, true, true]),
+            ("stop the music", [false, true, true]),
+            ("play music and stop", [false, false, true]),
+            ("play music", [false, false, false]),
+            ("stopwatch", [false, false, false]),
+        ];
+
+        for (text, expected) in cases {
+            let policies = [
+                StopWordPolicy::Only,
+                StopWordPolicy::Start,
+                StopWordPolicy::Anywhere,
+            ];
+            for (policy, expected) in policies.into_iter().zip(expected) {
+                assert_eq!(
+                    is_stop_command(text, &words, policy),
+                    expected,
+                    "{text:?} with {policy:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn uses_lowest_confidence_of_activation_phrase() {
+        let words = recognized(&[("Hey", 0.9), ("va", 0.5), ("stop", 0.1)]);
+        assert_eq!(activation_confidence("hey va", &words), Some(0.5));
+        assert_eq!(activation_confidence("hey", &words), Some(0.9));
+    }
+
+    #[test]
+    fn treats_confidences_that_do_not_match_the_phrase_as_zero() {
+        assert_eq!(activation_confidence("va", &[]), None);
+        assert_eq!(
+            activation_confidence("hey va", &recognized(&[("hey", 0.9)])),
+            Some(0.0)
+        );
+        assert_eq!(
+            activation_confidence("va", &recognized(&[("play", 0.9)])),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn longest_activation_word_wins() {
+        let words = set(&["va", "va assistant", "assistant"]);
+
+        assert_eq!(
+            find_activation_word("va assistant play music", &words),
+            Some("va assistant")
+        );
+        assert_eq!(find_activation_word("va play music", &words), Some("va"));
+        assert_eq!(find_activation_word("vast play", &words), None);
+    }
+
+    fn phrase() -> impl Strategy<Value = String> {
+        prop::collection::vec(
+            prop::sample::select(vec!["va", "hey", "assistant", "computer"]),
+            1..=3,
+        )
+        .prop_map(|tokens| tokens.join(" "))
+    }
+
+    proptest! {
+        #[test]
+        fn activation_word_extraction_is_deterministic(
+            words in prop::collection::vec(phrase(), 1..6),
+            text in phrase(),
+            tail in prop::sample::select(vec!["", " play music", " stop"]),
+        ) {
+            let text = format!("{text}{tail}");
+            let forward = find_activation_word(&text, words.iter());
+            let reversed = find_activation_word(&text, words.iter().rev());
+            prop_assert_eq!(forward, reversed);
+
+            let longest = words
+                .iter()
+                .filter(|word| text == **word || text.starts_with(&format!("{word} ")))
+                .map(String::len)
+                .max();
+            prop_assert_eq!(forward.map(str::len), longest);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use serde::Serialize;
+
+/// Process-wide counters exposed on `GET /metrics`.
+#[derive(Default)]
+pub(crate) struct Metrics {
+    pub(crate) queue_depth: AtomicUsize,
+    pub(crate) queued: AtomicU64,
+    pub(crate) dropped: AtomicU64,
+    pub(crate) forwarded: AtomicU64,
+    pub(crate) forward_failures: AtomicU64,
+    pub(crate) duplicates: AtomicU64,
+    pub(crate) low_confidence: AtomicU64,
+    pub(crate) unauthorized: AtomicU64,
+    pub(crate) rate_limited: AtomicU64,
+}
+
+#[derive(Serialize)]
+pub(crate) struct MetricsSnapshot {
+    queue_depth: usize,
+    queue_capacity: usize,
+    queued: u64,
+    dropped: u64,
+    forwarded: u64,
+    forward_failures: u64,
+    duplicates: u64,
+    low_confidence: u64,
+    unauthorized: u64,
+    rate_limited: u64,
+}
+
+impl Metrics {
+    pub(crate) fn snapshot(&self, queue_capacity: usize) -> MetricsSnapshot {
+        MetricsSnapshot {
+            queue_depth: self.queue_depth.load(Ordering::Relaxed),
+            queue_capacity,
+            queued: self.queued.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            forwarded: self.forwarded.load(Ordering::Relaxed),
+            forward_failures: self.forward_failures.load(Ordering::Relaxed),
+            duplicates: self.duplicates.load(Ordering::Relaxed),
+            low_confidence: self.low_confidence.load(Ordering::Relaxed),
+            unauthorized: self.unauthorized.load(Ordering::Relaxed),
+            rate_limited: self.rate_limited.load(Ordering::Relaxed),
+        }
+    }
+}
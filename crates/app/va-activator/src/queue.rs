@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::forward::{ForwardEvent, ForwardPayload, Forwarder};
+use crate::metrics::Metrics;
+use crate::rules::Route;
+
+/// How long a source's worker waits for another event before it shuts down.
+const WORKER_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+type Workers = Arc<Mutex<HashMap<String, Worker>>>;
+
+struct Job {
+    route: Route,
+    payload: ForwardPayload,
+    /// The source's cancel count when the job was queued; a later cancel drops the job.
+    generation: u64,
+}
+
+struct Worker {
+    sender: mpsc::UnboundedSender<Job>,
+    cancels: Arc<AtomicU64>,
+}
+
+#[derive(Debug)]
+pub(crate) struct QueueFull;
+
+/// Bounded in-process queue for accept-then-forward delivery.
+///
+/// Every source gets its own worker, so commands from one source are delivered in order while a
+/// slow source does not hold up the others. Workers shut down once their source has been idle for a
+/// while. The capacity bounds the events across all sources that have not been delivered yet.
+///
+/// Cancel events skip the queue: they are sent right away and drop the commands of their source
+/// that are still waiting.
+pub(crate) struct ForwardQueue {
+    forwarder: Arc<Forwarder>,
+    metrics: Arc<Metrics>,
+    capacity: usize,
+    idle_timeout: Duration,
+    workers: Workers,
+}
+
+impl ForwardQueue {
+    pub(crate) fn new(forwarder: Arc<Forwarder>, metrics: Arc<Metrics>, capacity: usize) -> Self {
+        Self {
+            forwarder,
+            metrics,
+            capacity,
+            idle_timeout: WORKER_IDLE_TIMEOUT,
+            workers: Workers::default(),
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub(crate) fn push(
+        &self,
+        source: &str,
+        route: Route,
+        payload: ForwardPayload,
+    ) -> Result<(), QueueFull> {
+        let reserved =
+            self.metrics
+                .queue_depth
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |depth| {
+                    (depth < self.capacity).then_some(depth + 1)
+                });
+        if reserved.is_err() {
+            self.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+            return Err(QueueFull);
+        }
+        self.metrics.queued.fetch_add(1, Ordering::Relaxed);
+
+        let mut workers = self.workers.lock().unwrap();
+        if matches!(payload.event, ForwardEvent::Cancel) {
+            if let Some(worker) = workers.get(source) {
+                worker.cancels.fetch_add(1, Ordering::SeqCst);
+            }
+            drop(workers);
+            let job = Job {
+                route,
+                payload,
+                generation: 0,
+            };
+            let (forwarder, metrics) = (self.forwarder.clone(), self.metrics.clone());
+            let source = source.to_string();
+            actix_web::rt::spawn(async move { deliver(&source, &job, &forwarder, &metrics).await });
+            return Ok(());
+        }
+
+        if let Some(worker) = workers.get(source) {
+            let job = Job {
+                route,
+                payload,
+                generation: worker.cancels.load(Ordering::SeqCst),
+            };
+            // A worker only leaves the map after it stopped receiving, so the send succeeds.
+            let _ = worker.sender.send(job);
+            return Ok(());
+        }
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let cancels = Arc::new(AtomicU64::new(0));
+        let _ = sender.send(Job {
+            route,
+            payload,
+            generation: 0,
+        });
+        workers.insert(
+            source.to_string(),
+            Worker {
+                sender,
+                cancels: cancels.clone(),
+            },
+        );
+        actix_web::rt::spawn(run_worker(
+            source.to_string(),
+            receiver,
+            cancels,
+            self.workers.clone(),
+            self.idle_timeout,
+            self.forwarder.clone(),
+            self.metrics.clone(),
+        ));
+        Ok(())
+    }
+}
+
+async fn run_worker(
+    source: String,
+    mut receiver: mpsc::UnboundedReceiver<Job>,
+    cancels: Arc<AtomicU64>,
+    workers: Workers,
+    idle_timeout: Duration,
+    forwarder: Arc<Forwarder>,
+    metrics: Arc<Metrics>,
+) {
+    loop {
+        let job = match actix_web::rt::time::timeout(idle_timeout, receiver.recv()).await {
+            Ok(Some(job)) => job,
+            Ok(None) => return,
+            Err(_) => {
+                // Unregister while holding the lock, so nothing is sent to this worker afterwards;
+                // jobs that were already sent are still delivered below.
+                let mut workers = workers.lock().unwrap();
+                if workers
+                    .get(&source)
+                    .is_some_and(|worker| Arc::ptr_eq(&worker.cancels, &cancels))
+                {
+                    workers.remove(&source);
+                }
+                drop(workers);
+                receiver.close();
+                continue;
+            }
+        };
+        if job.generation == cancels.load(Ordering::SeqCst) {
+            deliver(&source, &job, &forwarder, &metrics).await;
+        } else {
+            metrics.queue_depth.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+async fn deliver(source: &str, job: &Job, forwarder: &Forwarder, metrics: &Metrics) {
+    match forwarder.send(&job.route, &job.payload).await {
+        Ok(()) => {
+            metrics.forwarded.fetch_add(1, Ordering::Relaxed);
+        }
+        Err(err) => {
+            metrics.forward_failures.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                "queued forward for source {source} on route {} failed: {err}",
+                job.route.name
+            );
+        }
+    }
+    metrics.queue_depth.fetch_sub(1, Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ForwardSettings;
+
+    fn command(text: &str) -> ForwardPayload {
+        ForwardPayload {
+            event: ForwardEvent::Command,
+            text: Some(text.to_string()),
+            original_text: text.to_string(),
+            activation_word: "va".to_string(),
+            received_at: String::new(),
+            request_id: String::new(),
+            source: None,
+            speaker: None,
+            intent: None,
+            slots: Default::default(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn shuts_down_idle_workers() {
+        let forwarder = Forwarder::new(ForwardSettings {
+            connect_timeout: Duration::from_secs(1),
+            request_timeout: Duration::from_secs(1),
+            max_retries: 0,
+            retry_backoff: Duration::ZERO,
+            breaker_threshold: 0,
+            breaker_cooldown: Duration::from_secs(30),
+        })
+        .unwrap();
+        let metrics = Arc::new(Metrics::default());
+        let mut queue = ForwardQueue::new(Arc::new(forwarder), metrics.clone(), 4);
+        queue.idle_timeout = Duration::from_millis(50);
+        // Nothing listens on port 1, so delivery fails right away.
+        let route = Route::new("default", "http://127.0.0.1:1/webhook");
+
+        for source in ["kitchen", "office"] {
+            queue.push(source, route.clone(), command("one")).unwrap();
+        }
+        assert_eq!(queue.workers.lock().unwrap().len(), 2);
+
+        for _ in 0..100 {
+            if queue.workers.lock().unwrap().is_empty() {
+                break;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(queue.workers.lock().unwrap().is_empty());
+        assert_eq!(metrics.forward_failures.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.queue_depth.load(Ordering::SeqCst), 0);
+
+        queue.push("kitchen", route, command("two")).unwrap();
+        assert_eq!(queue.workers.lock().unwrap().len(), 1);
+    }
+}
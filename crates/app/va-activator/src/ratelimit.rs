@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Keys tracked before full buckets are pruned for the first time.
+const PRUNE_THRESHOLD: usize = 1024;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+struct Buckets {
+    by_key: HashMap<String, Bucket>,
+    /// Size at which full buckets are pruned next; doubles with the keys that are still limited,
+    /// so pruning stays cheap on average.
+    prune_at: usize,
+}
+
+/// Token bucket per key: each key may send `burst` requests at once and then `rate` requests per
+/// second.
+pub(crate) struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(rate: f64, burst: u32) -> Self {
+        Self {
+            rate,
+            burst: f64::from(burst.max(1)),
+            buckets: Mutex::new(Buckets {
+                by_key: HashMap::new(),
+                prune_at: PRUNE_THRESHOLD,
+            }),
+        }
+    }
+
+    /// Takes a token for the key. When none is left, returns how long until the next one is
+    /// available. A zero rate disables limiting.
+    pub(crate) fn check(&self, key: &str, now: Instant) -> Result<(), Duration> {
+        if self.rate <= 0.0 {
+            return Ok(());
+        }
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.by_key.len() >= buckets.prune_at {
+            // Buckets that have refilled completely behave like new ones and can be dropped.
+            buckets
+                .by_key
+                .retain(|_, bucket| self.refill(bucket, now) < self.burst);
+            buckets.prune_at = (buckets.by_key.len() * 2).max(PRUNE_THRESHOLD);
+        }
+        let bucket = buckets.by_key.entry(key.to_string()).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = self.refill(bucket, now);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            return Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate));
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+
+    fn refill(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.rate).min(self.burst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allows_burst_then_limits() {
+        let limiter = RateLimiter::new(2.0, 3);
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.check("10.0.0.1", now).is_ok());
+        }
+        assert_eq!(
+            limiter.check("10.0.0.1", now),
+            Err(Duration::from_millis(500))
+        );
+        assert!(limiter.check("10.0.0.2", now).is_ok());
+    }
+
+    #[test]
+    fn refills_over_time() {
+        let limiter = RateLimiter::new(2.0, 1);
+        let now = Instant::now();
+
+        assert!(limiter.check("kitchen", now).is_ok());
+        assert!(limiter
+            .check("kitchen", now + Duration::from_millis(250))
+            .is_err());
+        assert!(limiter
+            .check("kitchen", now + Duration::from_millis(500))
+            .is_ok());
+    }
+
+    #[test]
+    fn prunes_full_buckets_past_the_threshold() {
+        let limiter = RateLimiter::new(1.0, 1);
+        let now = Instant::now();
+
+        for key in 0..PRUNE_THRESHOLD {
+            assert!(limiter.check(&key.to_string(), now).is_ok());
+        }
+        assert_eq!(
+            limiter.buckets.lock().unwrap().by_key.len(),
+            PRUNE_THRESHOLD
+        );
+
+        // One second later every bucket is full again and is dropped on the next check.
+        let later = now + Duration::from_secs(1);
+        assert!(limiter.check("kitchen", later).is_ok());
+        assert_eq!(limiter.buckets.lock().unwrap().by_key.len(), 1);
+        assert!(limiter.check("kitchen", later).is_err());
+    }
+
+    #[test]
+    fn zero_rate_disables_limiting() {
+        let limiter = RateLimiter::new(0.0, 1);
+        let now = Instant::now();
+
+        for _ in 0..100 {
+            assert!(limiter.check("kitchen", now).is_ok());
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use tracing::{info, warn};
+
+use crate::error::Error;
+use crate::rules::{sorted, RuleDefaults, Rules};
+
+/// Why a change to the rules was not applied. In both cases the active rules are left as they were.
+#[derive(Debug)]
+pub(crate) enum UpdateError {
+    /// The change would leave the rules invalid.
+    Invalid(String),
+    /// The rules file could not be written.
+    Persist(Error),
+}
+
+impl fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid(message) => f.write_str(message),
+            Self::Persist(err) => write!(f, "failed to write rules file: {err}"),
+        }
+    }
+}
+
+/// Holds the active rules and swaps them atomically when the rules file changes.
+///
+/// Handlers take a snapshot with [`RuleStore::current`] and keep using it for the whole request, so
+/// a reload never mixes old and new rules within one decision.
+pub(crate) struct RuleStore {
+    path: Option<PathBuf>,
+    defaults: RuleDefaults,
+    current: RwLock<Arc<Rules>>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl RuleStore {
+    pub(crate) fn new(path: Option<PathBuf>, defaults: RuleDefaults, rules: Rules) -> Self {
+        let modified = path.as_ref().and_then(|path| modified_at(path));
+        Self {
+            path,
+            defaults,
+            current: RwLock::new(Arc::new(rules)),
+            modified: Mutex::new(modified),
+        }
+    }
+
+    pub(crate) fn current(&self) -> Arc<Rules> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Re-reads the rules file. If it does not validate, the active rules are kept and the error is
+    /// returned.
+    pub(crate) fn reload(&self) -> Result<(), Error> {
+        let Some(path) = &self.path else {
+            return Err("RULES_FILE is not set".into());
+        };
+
+        *self.modified.lock().unwrap() = modified_at(path);
+        let rules = Rules::load(path, &self.defaults)?;
+        *self.current.write().unwrap() = Arc::new(rules);
+        info!("rules reloaded from {}", path.display());
+        Ok(())
+    }
+
+    /// Applies `change` to a copy of the active rules and swaps the copy in. With `persist`, the
+    /// word lists are written to the rules file before the swap, so a failed write changes nothing.
+    pub(crate) fn update(
+        &self,
+        persist: bool,
+        change: impl FnOnce(&mut Rules) -> Result<(), String>,
+    ) -> Result<Arc<Rules>, UpdateError> {
+        let mut current = self.current.write().unwrap();
+        let mut rules = Rules::clone(&current);
+        change(&mut rules).map_err(UpdateError::Invalid)?;
+
+        if persist {
+            let path = self
+                .path
+                .as_ref()
+                .ok_or_else(|| UpdateError::Persist("RULES_FILE is not set".into()))?;
+            write_word_lists(path, &rules).map_err(UpdateError::Persist)?;
+            *self.modified.lock().unwrap() = modified_at(path);
+        }
+
+        let rules = Arc::new(rules);
+        *current = rules.clone();
+        Ok(rules)
+    }
+
+    /// Reloads when the file's modification time differs from the last load.
+    fn reload_if_changed(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        let modified = modified_at(path);
+        if modified == *self.modified.lock().unwrap() {
+            return;
+        }
+        if let Err(err) = self.reload() {
+            warn!("keeping previous rules: {err}");
+        }
+    }
+}
+
+/// Replaces the top-level word lists in the rules file and keeps everything else. Comments are not
+/// preserved.
+fn write_word_lists(path: &Path, rules: &Rules) -> Result<(), Error> {
+    let contents = fs::read_to_string(path)?;
+    let mut table = contents.parse::<toml::Table>()?;
+    for (key, words) in [
+        ("activation_words", &rules.activation_words),
+        ("stop_words", &rules.stop_words),
+    ] {
+        let words = sorted(words)
+            .into_iter()
+            .map(|word| toml::Value::String(word.to_string()))
+            .collect();
+        table.insert(key.to_string(), toml::Value::Array(words));
+    }
+
+    // Write next to the file and rename, so a reload never sees a half-written file.
+    let temp = path.with_extension("toml.tmp");
+    fs::write(&temp, toml::to_string(&table)?)?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Polls the rules file for changes every `interval`.
+pub(crate) async fn watch(store: Arc<RuleStore>, interval: Duration) {
+    let mut ticker = actix_web::rt::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        store.reload_if_changed();
+    }
+}
+
+/// Reloads the rules file on `SIGHUP`.
+#[cfg(unix)]
+pub(crate) async fn reload_on_sighup(store: Arc<RuleStore>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(err) => {
+            warn!("failed to listen for SIGHUP: {err}");
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        info!("SIGHUP received");
+        if let Err(err) = store.reload() {
+            warn!("keeping previous rules: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults() -> RuleDefaults {
+        RuleDefaults {
+            webhook_url: Some("http://127.0.0.1:8092/webhook".to_string()),
+            activation_words: Some(["va".to_string()].into_iter().collect()),
+            stop_words: Some(["stop".to_string()].into_iter().collect()),
+        }
+    }
+
+    fn temp_rules_file(name: &str, contents: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("va-activator-{}-{name}.toml", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn reload_swaps_rules() {
+        let path = temp_rules_file("swap", r#"activation_words = ["va"]"#);
+        let rules = Rules::load(&path, &defaults()).unwrap();
+        let store = RuleStore::new(Some(path.clone()), defaults(), rules);
+
+        fs::write(&path, r#"activation_words = ["computer"]"#).unwrap();
+        store.reload().unwrap();
+        assert!(store.current().activation_words.contains("computer"));
+        assert!(!store.current().activation_words.contains("va"));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn invalid_file_keeps_previous_rules() {
+        let path = temp_rules_file("invalid", r#"activation_words = ["va"]"#);
+        let rules = Rules::load(&path, &defaults()).unwrap();
+        let store = RuleStore::new(Some(path.clone()), defaults(), rules);
+        let before = store.current();
+
+        fs::write(&path, r#"activation_words = []"#).unwrap();
+        assert!(store.reload().is_err());
+        assert!(Arc::ptr_eq(&before, &store.current()));
+
+        fs::write(&path, "activation_words = [").unwrap();
+        store.reload_if_changed();
+        assert!(Arc::ptr_eq(&before, &store.current()));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn update_persists_word_lists() {
+        let path = temp_rules_file(
+            "persist",
+            r#"
+            activation_words = ["va"]
+
+            [default]
+            url = "http://127.0.0.1:8093/webhook"
+            "#,
+        );
+        let rules = Rules::load(&path, &defaults()).unwrap();
+        let store = RuleStore::new(Some(path.clone()), defaults(), rules);
+
+        store
+            .update(true, |rules| {
+                rules.activation_words.insert("computer".to_string());
+                Ok(())
+            })
+            .unwrap();
+
+        let reloaded = Rules::load(&path, &RuleDefaults::default()).unwrap();
+        assert_eq!(reloaded.activation_words, store.current().activation_words);
+        assert!(reloaded.stop_words.contains("stop"));
+        assert_eq!(
+            reloaded.routes.resolve("va", "anything").url,
+            "http://127.0.0.1:8093/webhook"
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejected_update_keeps_rules() {
+        let store = RuleStore::new(None, defaults(), Rules::from_defaults(&defaults()).unwrap());
+        let before = store.current();
+
+        let result = store.update(false, |rules| {
+            rules.stop_words.clear();
+            rules.check_word_lists()
+        });
+        assert!(matches!(result, Err(UpdateError::Invalid(_))));
+        assert!(Arc::ptr_eq(&before, &store.current()));
+
+        let result = store.update(true, |_| Ok(()));
+        assert!(matches!(result, Err(UpdateError::Persist(_))));
+        assert!(Arc::ptr_eq(&before, &store.current()));
+    }
+}
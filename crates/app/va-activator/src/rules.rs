@@ -0,0 +1,746 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use regex::Regex;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+
+use crate::config::normalize_word;
+use crate::error::Error;
+use crate::intents::{self, Intent, IntentEntry, IntentMatch, IntentView};
+use crate::matcher::starts_with_phrase;
+
+/// Contents of the rules file.
+///
+/// ```toml
+/// activation_words = ["va", "computer"]
+/// stop_words = ["stop", "cancel"]
+///
+/// [default]
+/// url = "http://127.0.0.1:8092/webhook"
+///
+/// [[routes]]
+/// name = "home"
+/// activation_word = "computer"
+/// url = "http://127.0.0.1:8123/api/webhook/va"
+/// timeout_ms = 2000
+/// headers = { Authorization = "Bearer secret" }
+///
+/// [sources.kitchen]
+/// activation_words = ["computer"]
+///
+/// [[intents]]
+/// name = "set_timer"
+/// pattern = "set (a )?timer for {duration}"
+/// ```
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    activation_words: Option<Vec<String>>,
+    stop_words: Option<Vec<String>>,
+    default: Option<RouteEntry>,
+    #[serde(default)]
+    routes: Vec<RouteRuleEntry>,
+    #[serde(default)]
+    sources: HashMap<String, SourceEntry>,
+    #[serde(default)]
+    intents: Vec<IntentEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SourceEntry {
+    activation_words: Option<Vec<String>>,
+    stop_words: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RouteEntry {
+    url: String,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    timeout_ms: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RouteRuleEntry {
+    name: String,
+    activation_word: Option<String>,
+    keyword: Option<String>,
+    pattern: Option<String>,
+    url: String,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    timeout_ms: Option<u64>,
+}
+
+/// Downstream target that receives forwarded events.
+#[derive(Clone, Debug)]
+pub(crate) struct Route {
+    pub(crate) name: String,
+    pub(crate) url: String,
+    pub(crate) headers: HeaderMap,
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl Route {
+    pub(crate) fn new(name: &str, url: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            url: url.to_string(),
+            headers: HeaderMap::new(),
+            timeout: None,
+        }
+    }
+
+    fn from_entry(name: &str, entry: RouteEntry) -> Result<Self, Error> {
+        let url = entry.url.trim().to_string();
+        if url.is_empty() {
+            return Err(format!("route {name}: url must not be empty").into());
+        }
+
+        let mut headers = HeaderMap::new();
+        for (key, value) in entry.headers {
+            let key = HeaderName::from_bytes(key.as_bytes())
+                .map_err(|err| format!("route {name}: invalid header name {key:?}: {err}"))?;
+            let value = HeaderValue::from_str(&value)
+                .map_err(|err| format!("route {name}: invalid value for header {key}: {err}"))?;
+            headers.insert(key, value);
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            url,
+            headers,
+            timeout: entry.timeout_ms.map(Duration::from_millis),
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+struct RouteRule {
+    activation_word: Option<String>,
+    keyword: Option<String>,
+    pattern: Option<Regex>,
+    route: Route,
+}
+
+impl RouteRule {
+    fn matches(&self, activation_word: &str, command: &str) -> bool {
+        self.activation_word
+            .as_ref()
+            .is_none_or(|word| word == activation_word)
+            && self
+                .keyword
+                .as_ref()
+                .is_none_or(|keyword| starts_with_phrase(&normalize_word(command), keyword))
+            && self
+                .pattern
+                .as_ref()
+                .is_none_or(|pattern| pattern.is_match(command))
+    }
+}
+
+/// Routing table: rules are checked in file order and the first match wins.
+#[derive(Clone, Debug)]
+pub(crate) struct Routes {
+    rules: Vec<RouteRule>,
+    default: Route,
+}
+
+impl Routes {
+    fn from_entries(
+        default: Option<RouteEntry>,
+        entries: Vec<RouteRuleEntry>,
+        fallback_url: Option<&str>,
+    ) -> Result<Self, Error> {
+        let default = match (default, fallback_url) {
+            (Some(entry), _) => Route::from_entry("default", entry)?,
+            (None, Some(url)) => Route::new("default", url),
+            (None, None) => return Err("no default route configured".into()),
+        };
+
+        let mut names = HashSet::new();
+        let rules = entries
+            .into_iter()
+            .map(|entry| {
+                let name = entry.name.trim();
+                if name == default.name {
+                    return Err(
+                        format!("route name {name} is reserved for the default route").into(),
+                    );
+                }
+                if !names.insert(name.to_string()) {
+                    return Err(format!("route name {name} is used more than once").into());
+                }
+                if entry.activation_word.is_none()
+                    && entry.keyword.is_none()
+                    && entry.pattern.is_none()
+                {
+                    return Err(format!(
+                        "route {name}: set at least one of activation_word, keyword or pattern"
+                    )
+                    .into());
+                }
+                let pattern = entry
+                    .pattern
+                    .as_deref()
+                    .map(Regex::new)
+                    .transpose()
+                    .map_err(|err| format!("route {name}: invalid pattern: {err}"))?;
+                let route = RouteEntry {
+                    url: entry.url,
+                    headers: entry.headers,
+                    timeout_ms: entry.timeout_ms,
+                };
+                Ok(RouteRule {
+                    activation_word: entry.activation_word.as_deref().map(normalize_word),
+                    keyword: entry.keyword.as_deref().map(normalize_word),
+                    pattern,
+                    route: Route::from_entry(name, route)?,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(Self { rules, default })
+    }
+
+    /// Picks the route for a command. Rules may match on the activation word, on the words the
+    /// command starts with (`keyword`) and on a regex over the command; every condition set on a
+    /// rule has to match.
+    pub(crate) fn resolve(&self, activation_word: &str, command: &str) -> &Route {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(activation_word, command))
+            .map_or(&self.default, |rule| &rule.route)
+    }
+}
+
+/// Word lists that replace the global ones for a single source.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SourceRules {
+    pub(crate) activation_words: Option<HashSet<String>>,
+    pub(crate) stop_words: Option<HashSet<String>>,
+}
+
+/// Settings from the environment that the rules file may override.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RuleDefaults {
+    pub(crate) webhook_url: Option<String>,
+    pub(crate) activation_words: Option<HashSet<String>>,
+    pub(crate) stop_words: Option<HashSet<String>>,
+}
+
+/// Word lists and routing; the part of the configuration that can change while the service runs.
+#[derive(Clone, Debug)]
+pub(crate) struct Rules {
+    pub(crate) activation_words: HashSet<String>,
+    pub(crate) stop_words: HashSet<String>,
+    pub(crate) routes: Routes,
+    pub(crate) sources: HashMap<String, SourceRules>,
+    pub(crate) intents: Vec<Intent>,
+}
+
+impl Rules {
+    /// Builds the rules from the environment alone, for when there is no rules file.
+    pub(crate) fn from_defaults(defaults: &RuleDefaults) -> Result<Self, Error> {
+        Self::parse("", defaults)
+    }
+
+    pub(crate) fn load(path: &Path, defaults: &RuleDefaults) -> Result<Self, Error> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("failed to read {}: {err}", path.display()))?;
+        Self::parse(&contents, defaults).map_err(|err| format!("{}: {err}", path.display()).into())
+    }
+
+    /// Parses the rules file. Word lists and the `[default]` route fall back to `defaults` when the
+    /// file does not set them.
+    pub(crate) fn parse(contents: &str, defaults: &RuleDefaults) -> Result<Self, Error> {
+        let file: RulesFile = toml::from_str(contents)?;
+
+        let activation_words = word_set("activation_words", file.activation_words)?
+            .or_else(|| defaults.activation_words.clone())
+            .ok_or("no activation words configured")?;
+        let stop_words = word_set("stop_words", file.stop_words)?
+            .or_else(|| defaults.stop_words.clone())
+            .ok_or("no stop words configured")?;
+        let routes =
+            Routes::from_entries(file.default, file.routes, defaults.webhook_url.as_deref())?;
+
+        let sources = file
+            .sources
+            .into_iter()
+            .map(|(source, entry)| {
+                let source_rules = SourceRules {
+                    activation_words: word_set(
+                        &format!("source {source}: activation_words"),
+                        entry.activation_words,
+                    )?,
+                    stop_words: word_set(
+                        &format!("source {source}: stop_words"),
+                        entry.stop_words,
+                    )?,
+                };
+                Ok((source, source_rules))
+            })
+            .collect::<Result<_, Error>>()?;
+        let intents = file
+            .intents
+            .into_iter()
+            .map(Intent::from_entry)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut intent_names = HashSet::new();
+        if let Some(intent) = intents
+            .iter()
+            .find(|intent| !intent_names.insert(intent.name()))
+        {
+            return Err(format!("intent name {} is used more than once", intent.name()).into());
+        }
+
+        let rules = Self {
+            activation_words,
+            stop_words,
+            routes,
+            sources,
+            intents,
+        };
+        rules.check_word_lists()?;
+        Ok(rules)
+    }
+
+    pub(crate) fn source(&self, source: Option<&str>) -> Option<&SourceRules> {
+        source.and_then(|source| self.sources.get(source))
+    }
+
+    pub(crate) fn match_intent(&self, command: &str) -> Option<IntentMatch> {
+        intents::match_intent(&self.intents, command)
+    }
+
+    /// Checks the word lists, globally and as each source sees them: both need a word, and no word
+    /// may be both an activation word and a stop word.
+    pub(crate) fn check_word_lists(&self) -> Result<(), String> {
+        check_word_lists(&self.activation_words, &self.stop_words)?;
+        let mut sources = self.sources.iter().collect::<Vec<_>>();
+        sources.sort_unstable_by_key(|(source, _)| *source);
+        for (source, rules) in sources {
+            check_word_lists(
+                rules
+                    .activation_words
+                    .as_ref()
+                    .unwrap_or(&self.activation_words),
+                rules.stop_words.as_ref().unwrap_or(&self.stop_words),
+            )
+            .map_err(|err| format!("source {source}: {err}"))?;
+        }
+        Ok(())
+    }
+}
+
+fn check_word_lists(
+    activation_words: &HashSet<String>,
+    stop_words: &HashSet<String>,
+) -> Result<(), String> {
+    if activation_words.is_empty() {
+        return Err("activation_words must contain at least one word".to_string());
+    }
+    if stop_words.is_empty() {
+        return Err("stop_words must contain at least one word".to_string());
+    }
+    let mut overlap = activation_words
+        .intersection(stop_words)
+        .collect::<Vec<_>>();
+    if !overlap.is_empty() {
+        overlap.sort();
+        return Err(format!(
+            "words are both activation and stop words: {overlap:?}"
+        ));
+    }
+    Ok(())
+}
+
+fn word_set(field: &str, words: Option<Vec<String>>) -> Result<Option<HashSet<String>>, Error> {
+    let Some(words) = words else {
+        return Ok(None);
+    };
+    let words = words
+        .iter()
+        .map(|word| normalize_word(word))
+        .filter(|word| !word.is_empty())
+        .collect::<HashSet<_>>();
+    if words.is_empty() {
+        return Err(format!("{field} must contain at least one word").into());
+    }
+    Ok(Some(words))
+}
+
+/// Serializable view of the active rules for `GET /config`. Route headers are left out since they
+/// may carry credentials.
+#[derive(Serialize)]
+pub(crate) struct RulesView<'a> {
+    activation_words: Vec<&'a str>,
+    stop_words: Vec<&'a str>,
+    routes: Vec<RouteView<'a>>,
+    default_route: RouteView<'a>,
+    sources: BTreeMap<&'a str, SourceView<'a>>,
+    intents: Vec<IntentView<'a>>,
+}
+
+#[derive(Serialize)]
+struct RouteView<'a> {
+    name: &'a str,
+    url: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    activation_word: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keyword: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pattern: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeout_ms: Option<u128>,
+}
+
+#[derive(Serialize)]
+struct SourceView<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    activation_words: Option<Vec<&'a str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_words: Option<Vec<&'a str>>,
+}
+
+impl<'a> RouteView<'a> {
+    fn new(route: &'a Route) -> Self {
+        Self {
+            name: &route.name,
+            url: &route.url,
+            activation_word: None,
+            keyword: None,
+            pattern: None,
+            timeout_ms: route.timeout.map(|timeout| timeout.as_millis()),
+        }
+    }
+}
+
+impl Rules {
+    pub(crate) fn view(&self) -> RulesView<'_> {
+        RulesView {
+            activation_words: sorted(&self.activation_words),
+            stop_words: sorted(&self.stop_words),
+            routes: self
+                .routes
+                .rules
+                .iter()
+                .map(|rule| RouteView {
+                    activation_word: rule.activation_word.as_deref(),
+                    keyword: rule.keyword.as_deref(),
+                    pattern: rule.pattern.as_ref().map(Regex::as_str),
+                    ..RouteView::new(&rule.route)
+                })
+                .collect(),
+            default_route: RouteView::new(&self.routes.default),
+            sources: self
+                .sources
+                .iter()
+                .map(|(source, rules)| {
+                    let view = SourceView {
+                        activation_words: rules.activation_words.as_ref().map(sorted),
+                        stop_words: rules.stop_words.as_ref().map(sorted),
+                    };
+                    (source.as_str(), view)
+                })
+                .collect(),
+            intents: self.intents.iter().map(Intent::view).collect(),
+        }
+    }
+}
+
+pub(crate) fn sorted(words: &HashSet<String>) -> Vec<&str> {
+    let mut words = words.iter().map(String::as_str).collect::<Vec<_>>();
+    words.sort_unstable();
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults(webhook_url: Option<&str>) -> RuleDefaults {
+        RuleDefaults {
+            webhook_url: webhook_url.map(str::to_string),
+            activation_words: Some(["va".to_string()].into_iter().collect()),
+            stop_words: Some(["stop".to_string()].into_iter().collect()),
+        }
+    }
+
+    const RULES: &str = r#"
+        [default]
+        url = "http://127.0.0.1:8092/webhook"
+        timeout_ms = 30000
+
+        [[routes]]
+        name = "home"
+        activation_word = "Computer"
+        url = "http://127.0.0.1:8123/webhook"
+        headers = { Authorization = "Bearer secret" }
+        timeout_ms = 2000
+
+        [[routes]]
+        name = "lights"
+        keyword = "lights"
+        url = "http://127.0.0.1:8124/webhook"
+
+        [[routes]]
+        name = "switches"
+        keyword = "Turn  on"
+        url = "http://127.0.0.1:8126/webhook"
+
+        [[routes]]
+        name = "timers"
+        activation_word = "va"
+        pattern = "^set (a )?timer"
+        url = "http://127.0.0.1:8125/webhook"
+
+        [[intents]]
+        name = "set_timer"
+        pattern = "set (a )?timer for {duration}"
+    "#;
+
+    #[test]
+    fn resolves_first_matching_rule() {
+        let routes = Rules::parse(RULES, &defaults(None)).unwrap().routes;
+
+        assert_eq!(routes.resolve("computer", "lights off").name, "home");
+        assert_eq!(routes.resolve("va", "lights off").name, "lights");
+        assert_eq!(
+            routes.resolve("va", "set a timer for five minutes").name,
+            "timers"
+        );
+        assert_eq!(routes.resolve("assistant", "set a timer").name, "default");
+        assert_eq!(routes.resolve("va", "turn the lights off").name, "default");
+        assert_eq!(routes.resolve("va", "turn on the radio").name, "switches");
+        assert_eq!(routes.resolve("va", "turn online mode").name, "default");
+    }
+
+    #[test]
+    fn parses_route_settings() {
+        let routes = Rules::parse(RULES, &defaults(None)).unwrap().routes;
+
+        let home = routes.resolve("computer", "anything");
+        assert_eq!(home.url, "http://127.0.0.1:8123/webhook");
+        assert_eq!(home.headers["authorization"], "Bearer secret");
+        assert_eq!(home.timeout, Some(Duration::from_secs(2)));
+
+        let default = routes.resolve("va", "anything");
+        assert_eq!(default.timeout, Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn falls_back_to_webhook_url_for_default_route() {
+        let routes = Rules::parse("", &defaults(Some("http://127.0.0.1:8092/webhook")))
+            .unwrap()
+            .routes;
+        assert_eq!(
+            routes.resolve("va", "play music").url,
+            "http://127.0.0.1:8092/webhook"
+        );
+
+        assert!(Rules::parse("", &defaults(None)).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        let unconditional = r#"
+            [[routes]]
+            name = "all"
+            url = "http://127.0.0.1:8123/webhook"
+        "#;
+        assert!(Rules::parse(unconditional, &defaults(Some("http://x"))).is_err());
+
+        let bad_pattern = r#"
+            [[routes]]
+            name = "broken"
+            pattern = "(unclosed"
+            url = "http://127.0.0.1:8123/webhook"
+        "#;
+        assert!(Rules::parse(bad_pattern, &defaults(Some("http://x"))).is_err());
+
+        let typo = r#"
+            [[routes]]
+            name = "typo"
+            activation_words = "computer"
+            url = "http://127.0.0.1:8123/webhook"
+        "#;
+        assert!(Rules::parse(typo, &defaults(Some("http://x"))).is_err());
+
+        let duplicate = r#"
+            [[routes]]
+            name = "home"
+            keyword = "lights"
+            url = "http://127.0.0.1:8123/webhook"
+
+            [[routes]]
+            name = "home"
+            keyword = "heating"
+            url = "http://127.0.0.1:8124/webhook"
+        "#;
+        assert!(Rules::parse(duplicate, &defaults(Some("http://x"))).is_err());
+
+        let reserved = r#"
+            [[routes]]
+            name = "default"
+            keyword = "lights"
+            url = "http://127.0.0.1:8123/webhook"
+        "#;
+        assert!(Rules::parse(reserved, &defaults(Some("http://x"))).is_err());
+    }
+
+    #[test]
+    fn parses_source_overrides() {
+        let rules = Rules::parse(
+            r#"
+            [sources.kitchen]
+            activation_words = ["Computer", " hey  kitchen "]
+
+            [sources.office]
+            stop_words = ["enough"]
+            "#,
+            &defaults(Some("http://x")),
+        )
+        .unwrap();
+
+        let kitchen = rules.source(Some("kitchen")).unwrap();
+        let expected: HashSet<String> = ["computer", "hey kitchen"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(kitchen.activation_words.as_ref(), Some(&expected));
+        assert!(kitchen.stop_words.is_none());
+        assert!(rules
+            .source(Some("office"))
+            .unwrap()
+            .activation_words
+            .is_none());
+        assert!(rules.source(Some("hallway")).is_none());
+        assert!(rules.source(None).is_none());
+
+        let empty = r#"
+            [sources.kitchen]
+            activation_words = [" "]
+        "#;
+        assert!(Rules::parse(empty, &defaults(Some("http://x"))).is_err());
+    }
+
+    #[test]
+    fn file_word_lists_override_defaults() {
+        let rules = Rules::parse(
+            r#"
+            activation_words = ["Computer"]
+            "#,
+            &defaults(Some("http://x")),
+        )
+        .unwrap();
+
+        assert_eq!(sorted(&rules.activation_words), ["computer"]);
+        assert_eq!(sorted(&rules.stop_words), ["stop"]);
+
+        let no_words = RuleDefaults {
+            webhook_url: Some("http://x".to_string()),
+            ..RuleDefaults::default()
+        };
+        assert!(Rules::parse("", &no_words).is_err());
+        assert!(Rules::parse(r#"activation_words = []"#, &defaults(Some("http://x"))).is_err());
+    }
+
+    #[test]
+    fn rejects_overlapping_word_lists() {
+        let global = r#"activation_words = ["va", "stop"]"#;
+        let err = Rules::parse(global, &defaults(Some("http://x")))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            r#"words are both activation and stop words: ["stop"]"#
+        );
+
+        let source = r#"
+            [sources.kitchen]
+            stop_words = ["va"]
+        "#;
+        let err = Rules::parse(source, &defaults(Some("http://x")))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            r#"source kitchen: words are both activation and stop words: ["va"]"#
+        );
+
+        let own_lists = r#"
+            [sources.kitchen]
+            activation_words = ["stop"]
+            stop_words = ["enough"]
+        "#;
+        assert!(Rules::parse(own_lists, &defaults(Some("http://x"))).is_ok());
+    }
+
+    #[test]
+    fn view_lists_active_rules() {
+        let rules = Rules::parse(RULES, &defaults(None)).unwrap();
+        let view = serde_json::to_value(rules.view()).unwrap();
+
+        assert_eq!(view["activation_words"], serde_json::json!(["va"]));
+        assert_eq!(view["routes"][0]["name"], "home");
+        assert_eq!(view["routes"][0]["activation_word"], "computer");
+        assert!(view["routes"][0].get("headers").is_none());
+        assert_eq!(view["routes"][3]["pattern"], "^set (a )?timer");
+        assert_eq!(view["default_route"]["timeout_ms"], 30000);
+        assert_eq!(view["intents"][0]["name"], "set_timer");
+        assert_eq!(
+            view["intents"][0]["pattern"],
+            "set (a )?timer for {duration}"
+        );
+    }
+
+    #[test]
+    fn parses_intents() {
+        let rules = Rules::parse(RULES, &defaults(None)).unwrap();
+
+        let matched = rules.match_intent("set a timer for five minutes").unwrap();
+        assert_eq!(matched.name, "set_timer");
+        assert_eq!(matched.slots["duration"], "five minutes");
+        assert!(rules.match_intent("play music").is_none());
+
+        let bad_intent = r#"
+            [[intents]]
+            name = "broken"
+            pattern = "set (timer for {duration}"
+        "#;
+        let err = Rules::parse(bad_intent, &defaults(Some("http://x")))
+            .err()
+            .unwrap();
+        assert!(
+            err.to_string()
+                .starts_with("intent broken: invalid pattern"),
+            "{err}"
+        );
+
+        let duplicate = r#"
+            [[intents]]
+            name = "set_timer"
+            pattern = "set (a )?timer for {duration}"
+
+            [[intents]]
+            name = "set_timer"
+            pattern = "start a timer for {duration}"
+        "#;
+        let err = Rules::parse(duplicate, &defaults(Some("http://x")))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "intent name set_timer is used more than once"
+        );
+    }
+}
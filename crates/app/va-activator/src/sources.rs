@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+struct SourceState {
+    /// Activation word that opened the listening window and when the window closes.
+    listening: Option<(String, Instant)>,
+}
+
+/// State kept per audio source.
+#[derive(Default)]
+pub(crate) struct Sources {
+    states: Mutex<HashMap<String, SourceState>>,
+}
+
+impl Sources {
+    /// Opens a listening window: the next utterance from the source within `window` is taken as a
+    /// command even without an activation word. Expired windows of other sources are dropped, so
+    /// the map does not grow with every source that was ever seen.
+    pub(crate) fn open_window(
+        &self,
+        source: &str,
+        activation_word: &str,
+        now: Instant,
+        window: Duration,
+    ) {
+        let mut states = self.states.lock().unwrap();
+        states.retain(|_, state| {
+            state
+                .listening
+                .as_ref()
+                .is_some_and(|(_, until)| now < *until)
+        });
+        let state = states.entry(source.to_string()).or_default();
+        state.listening = Some((activation_word.to_string(), now + window));
+    }
+
+    /// Closes the source's listening window and returns the activation word that opened it, unless
+    /// it has expired.
+    pub(crate) fn take_window(&self, source: &str, now: Instant) -> Option<String> {
+        let mut states = self.states.lock().unwrap();
+        let (activation_word, until) = states.remove(source)?.listening?;
+        (now < until).then_some(activation_word)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_is_consumed_once() {
+        let sources = Sources::default();
+        let now = Instant::now();
+
+        sources.open_window("kitchen", "va", now, Duration::from_secs(5));
+        assert_eq!(
+            sources
+                .take_window("kitchen", now + Duration::from_secs(1))
+                .as_deref(),
+            Some("va")
+        );
+        assert_eq!(
+            sources.take_window("kitchen", now + Duration::from_secs(2)),
+            None
+        );
+    }
+
+    #[test]
+    fn window_expires() {
+        let sources = Sources::default();
+        let now = Instant::now();
+
+        sources.open_window("kitchen", "va", now, Duration::from_secs(5));
+        assert_eq!(
+            sources.take_window("kitchen", now + Duration::from_secs(5)),
+            None
+        );
+    }
+
+    #[test]
+    fn windows_are_kept_per_source() {
+        let sources = Sources::default();
+        let now = Instant::now();
+
+        sources.open_window("kitchen", "va", now, Duration::from_secs(5));
+        assert_eq!(sources.take_window("office", now), None);
+        assert_eq!(sources.take_window("kitchen", now).as_deref(), Some("va"));
+    }
+
+    #[test]
+    fn drops_closed_and_expired_windows() {
+        let sources = Sources::default();
+        let now = Instant::now();
+
+        sources.open_window("kitchen", "va", now, Duration::from_secs(5));
+        sources.open_window("office", "va", now, Duration::from_secs(5));
+        sources.take_window("kitchen", now);
+        assert_eq!(sources.states.lock().unwrap().len(), 1);
+
+        sources.open_window(
+            "hallway",
+            "va",
+            now + Duration::from_secs(5),
+            Duration::from_secs(5),
+        );
+        let states = sources.states.lock().unwrap();
+        assert_eq!(states.keys().collect::<Vec<_>>(), ["hallway"]);
+    }
+}
@@ -0,0 +1,180 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde_json::{Map, Value};
+use va_skills::{CommandDescriptor, CommandResponse, CommandsResponse, ExecuteRequest};
+
+use crate::error::Error;
+
+/// Client for the va-actions service.
+pub(crate) struct Actions<'a> {
+    client: &'a reqwest::Client,
+    base_url: &'a str,
+}
+
+impl<'a> Actions<'a> {
+    pub(crate) fn new(client: &'a reqwest::Client, base_url: &'a str) -> Self {
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/'),
+        }
+    }
+
+    pub(crate) async fn commands(&self) -> Result<Vec<CommandDescriptor>, Error> {
+        let response: CommandsResponse = self
+            .client
+            .get(format!("{}/commands", self.base_url))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response.commands)
+    }
+
+    pub(crate) async fn execute(&self, request: &ExecuteRequest) -> Result<CommandResponse, Error> {
+        let response = self
+            .client
+            .post(format!("{}/execute", self.base_url))
+            .json(request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response)
+    }
+}
+
+type CachedCommands = Option<(Instant, Arc<Vec<CommandDescriptor>>)>;
+
+/// va-actions' command list, fetched again once it is older than the TTL or after a command failed.
+pub(crate) struct CommandCache {
+    ttl: Duration,
+    cached: Mutex<CachedCommands>,
+}
+
+impl CommandCache {
+    pub(crate) fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            cached: Mutex::new(None),
+        }
+    }
+
+    pub(crate) async fn get(
+        &self,
+        actions: &Actions<'_>,
+        now: Instant,
+    ) -> Result<Arc<Vec<CommandDescriptor>>, Error> {
+        if let Some((fetched, commands)) = &*self.cached.lock().unwrap() {
+            if now.duration_since(*fetched) < self.ttl {
+                return Ok(commands.clone());
+            }
+        }
+        let commands = Arc::new(actions.commands().await?);
+        *self.cached.lock().unwrap() = Some((now, commands.clone()));
+        Ok(commands)
+    }
+
+    /// Makes the next [`CommandCache::get`] fetch the list, e.g. after va-actions was restarted
+    /// with other commands.
+    pub(crate) fn invalidate(&self) {
+        *self.cached.lock().unwrap() = None;
+    }
+}
+
+/// Builds the `/execute` request for a command from the arguments the model chose. Commands unknown
+/// to va-skills are rejected here rather than by va-actions.
+pub(crate) fn execute_request(command: &str, arguments: Value) -> Result<ExecuteRequest, Error> {
+    let mut request = match arguments {
+        Value::Object(arguments) => arguments,
+        Value::Null => Map::new(),
+        other => {
+            return Err(format!("arguments for {command} must be an object, got {other}").into())
+        }
+    };
+    request.insert("command".to_string(), Value::String(command.to_string()));
+    serde_json::from_value(Value::Object(request))
+        .map_err(|err| format!("invalid call to {command}: {err}").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use serde_json::json;
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use va_skills::CommandRequest;
+
+    #[actix_web::test]
+    async fn caches_the_command_list() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let requests_clone = requests.clone();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = HttpServer::new(move || {
+            let requests = requests_clone.clone();
+            App::new().route(
+                "/commands",
+                web::get().to(move || {
+                    requests.fetch_add(1, Ordering::SeqCst);
+                    async {
+                        HttpResponse::Ok().json(CommandsResponse {
+                            instructions: String::new(),
+                            commands: Vec::new(),
+                        })
+                    }
+                }),
+            )
+        })
+        .shutdown_timeout(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let client = reqwest::Client::new();
+        let base_url = format!("http://{addr}");
+        let actions = Actions::new(&client, &base_url);
+        let cache = CommandCache::new(Duration::from_secs(60));
+        let now = Instant::now();
+
+        cache.get(&actions, now).await.unwrap();
+        cache
+            .get(&actions, now + Duration::from_secs(59))
+            .await
+            .unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        cache
+            .get(&actions, now + Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        cache.invalidate();
+        cache
+            .get(&actions, now + Duration::from_secs(61))
+            .await
+            .unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+        handle.stop(true).await;
+    }
+
+    #[test]
+    fn builds_execute_request_from_tool_arguments() {
+        let request = execute_request("time.now", json!({})).unwrap();
+        assert!(matches!(request.command, CommandRequest::TimeNow));
+        assert!(matches!(
+            execute_request("date.now", Value::Null).unwrap().command,
+            CommandRequest::DateNow
+        ));
+
+        assert!(execute_request("lights.on", json!({})).is_err());
+        assert!(execute_request("time.now", json!("now")).is_err());
+    }
+}
@@ -0,0 +1,348 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Instant;
+
+use actix_web::http::StatusCode;
+use chrono::Local;
+use serde::Serialize;
+use serde_json::{json, Map};
+use tracing::{info, warn};
+use va_skills::{CommandDescriptor, CommandResponse};
+
+use crate::actions::{execute_request, Actions};
+use crate::backend::{BackendError, ChatMessage, ChatRequest, GenerationOptions, Role, Tool};
+use crate::error::Error;
+use crate::fast_path::FastPathMatch;
+use crate::intent::{Intent, OutputFormat};
+use crate::prompt::{PromptValues, Variable};
+use crate::AppState;
+
+/// Model turns that may request tool calls before it has to answer.
+const MAX_TOOL_ROUNDS: usize = 4;
+
+/// The model's final text and the results of the commands it ran to get there, in call order.
+pub(crate) struct Answer {
+    /// The intent's reply for [`OutputFormat::Intent`].
+    pub(crate) text: String,
+    pub(crate) results: Vec<CommandResponse>,
+    /// Set for [`OutputFormat::Intent`].
+    pub(crate) intent: Option<Intent>,
+    pub(crate) path: AnswerPath,
+}
+
+/// How a command was answered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AnswerPath {
+    /// A fast path phrase ran the command directly.
+    FastPath,
+    Model,
+}
+
+#[derive(Debug)]
+pub(crate) enum AnswerError {
+    Backend(BackendError),
+    /// The model was still calling tools when it ran out of rounds.
+    TooManyToolRounds,
+    /// The model's reply did not match the intent schema, also after being asked to correct it.
+    InvalidOutput(String),
+}
+
+impl AnswerError {
+    /// Status for the webhook response: the failure is upstream, never in the request itself.
+    pub(crate) fn http_status(&self) -> StatusCode {
+        match self {
+            Self::Backend(BackendError::Unavailable(_)) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Backend(BackendError::Timeout) => StatusCode::GATEWAY_TIMEOUT,
+            Self::Backend(
+                BackendError::Status { .. }
+                | BackendError::Failed(_)
+                | BackendError::InvalidResponse(_),
+            )
+            | Self::TooManyToolRounds
+            | Self::InvalidOutput(_) => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+impl fmt::Display for AnswerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Backend(err) => err.fmt(f),
+            Self::TooManyToolRounds => {
+                write!(f, "model kept calling tools after {MAX_TOOL_ROUNDS} rounds")
+            }
+            Self::InvalidOutput(err) => {
+                write!(f, "model reply does not match the intent schema: {err}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AnswerError {}
+
+/// Answers a command, letting the model call va-actions commands as tools along the way. `on_text`
+/// gets the model's text as it is generated; text written before a tool call, such as "Let me
+/// check", is followed by a newline so it does not run into the answer.
+///
+/// Commands matching a fast path phrase are run directly and answered without the model, unless
+/// va-actions fails.
+///
+/// With a session id, the session's earlier turns are part of the conversation and the answer is
+/// added to them. With [`OutputFormat::Intent`] the model has to answer with an [`Intent`]; an
+/// invalid one is sent back once for correction.
+pub(crate) async fn answer(
+    state: &AppState,
+    command: &str,
+    session: Option<&str>,
+    options: &GenerationOptions,
+    format: OutputFormat,
+    on_text: &mut dyn FnMut(&str),
+) -> Result<Answer, AnswerError> {
+    let actions = Actions::new(&state.client, &state.config.actions_base_url);
+    if let Some(matched) = state.config.fast_path.find(command) {
+        if let Some((text, response)) = answer_directly(&actions, &matched).await {
+            on_text(&text);
+            if let Some(session) = session {
+                state
+                    .sessions
+                    .record(session, command, &text, Instant::now());
+            }
+            let intent = (format == OutputFormat::Intent).then(|| Intent {
+                intent: tool_name(matched.command()),
+                slots: Map::new(),
+                reply: text.clone(),
+            });
+            return Ok(Answer {
+                text,
+                results: vec![response],
+                intent,
+                path: AnswerPath::FastPath,
+            });
+        }
+    }
+
+    // Without the command list the model can still answer whatever does not need a tool. A model
+    // that recently refused tools gets none.
+    let tools_refused = state
+        .tools_refused_at
+        .lock()
+        .unwrap()
+        .is_some_and(|at| at.elapsed() < state.config.commands_ttl);
+    let commands = if tools_refused {
+        Arc::default()
+    } else {
+        state
+            .commands
+            .get(&actions, Instant::now())
+            .await
+            .unwrap_or_else(|err| {
+                warn!("failed to list va-actions commands: {err}");
+                Arc::default()
+            })
+    };
+    let mut tools = commands.iter().map(tool).collect::<Vec<_>>();
+
+    let history = session
+        .map(|session| state.sessions.history(session, Instant::now()))
+        .unwrap_or_default();
+    let template = state.prompts.current();
+    let system_prompt = template.render(&PromptValues {
+        command,
+        time: &Local::now().format("%A, %Y-%m-%d %H:%M").to_string(),
+        locale: &state.config.locale,
+        tools: &describe_tools(&commands),
+        history: &describe_history(&history),
+    });
+
+    let mut messages = vec![ChatMessage::new(Role::System, system_prompt)];
+    // A template that embeds the history gets it only there.
+    if !template.uses(Variable::History) {
+        messages.extend(history);
+    }
+    messages.push(ChatMessage::new(Role::User, command));
+    let schema = (format == OutputFormat::Intent).then(Intent::schema);
+    let mut results = Vec::new();
+    let mut tool_rounds = 0;
+    let mut corrected = false;
+    loop {
+        let request = ChatRequest {
+            messages: &messages,
+            tools: &tools,
+            options,
+            format: schema.as_ref(),
+        };
+        let reply = match state.backend.chat(request, on_text).await {
+            Ok(reply) => reply,
+            Err(err) if err.rejects_tools() && !tools.is_empty() => {
+                warn!("model does not support tools, answering without va-actions commands: {err}");
+                *state.tools_refused_at.lock().unwrap() = Some(Instant::now());
+                tools.clear();
+                continue;
+            }
+            Err(err) => return Err(AnswerError::Backend(err)),
+        };
+        if reply.tool_calls.is_empty() {
+            let (text, intent) = match format {
+                OutputFormat::Text => (reply.content.trim().to_string(), None),
+                OutputFormat::Intent => match Intent::parse(&reply.content) {
+                    Ok(intent) => (intent.reply.trim().to_string(), Some(intent)),
+                    Err(err) if !corrected => {
+                        warn!("model reply does not match the intent schema, asking again: {err}");
+                        corrected = true;
+                        messages.push(reply);
+                        messages.push(ChatMessage::new(Role::User, correction(&err)));
+                        continue;
+                    }
+                    Err(err) => return Err(AnswerError::InvalidOutput(err)),
+                },
+            };
+            if let Some(session) = session {
+                state
+                    .sessions
+                    .record(session, command, &text, Instant::now());
+            }
+            return Ok(Answer {
+                text,
+                results,
+                intent,
+                path: AnswerPath::Model,
+            });
+        }
+        if tool_rounds == MAX_TOOL_ROUNDS {
+            return Err(AnswerError::TooManyToolRounds);
+        }
+        tool_rounds += 1;
+        on_text("\n");
+
+        let calls = reply.tool_calls.clone();
+        messages.push(reply);
+        for call in calls {
+            let arguments = call.function.arguments.clone();
+            let content = match call_tool(&actions, &commands, &call.function.name, arguments).await
+            {
+                Ok(response) => {
+                    let content = serde_json::to_string(&response).unwrap_or_default();
+                    results.push(response);
+                    content
+                }
+                Err(err) => {
+                    warn!("tool call {} failed: {err}", call.function.name);
+                    // The command list may be out of date, e.g. after va-actions was updated.
+                    state.commands.invalidate();
+                    json!({ "error": err.to_string() }).to_string()
+                }
+            };
+            messages.push(ChatMessage::tool_result(&call, content));
+        }
+    }
+}
+
+/// Runs a fast path command and formats its reply. Returns `None` on failure, leaving the command
+/// to the model.
+async fn answer_directly(
+    actions: &Actions<'_>,
+    matched: &FastPathMatch<'_>,
+) -> Option<(String, CommandResponse)> {
+    info!("fast path: executing {}", matched.command());
+    let response = match actions.execute(matched.request()).await {
+        Ok(response) => response,
+        Err(err) => {
+            warn!(
+                "fast path command {} failed, asking the model: {err}",
+                matched.command()
+            );
+            return None;
+        }
+    };
+    let Some(text) = matched.reply(&response) else {
+        warn!(
+            "fast path command {} returned an unexpected result, asking the model",
+            matched.command()
+        );
+        return None;
+    };
+    Some((text, response))
+}
+
+/// Asks the model to fix a reply that is not a valid [`Intent`].
+fn correction(err: &str) -> String {
+    format!(
+        "Your reply is not valid: {err}. Answer again with only a JSON object with the fields \
+         \"intent\" (string), \"slots\" (object) and \"reply\" (string)."
+    )
+}
+
+/// Tool names are restricted to letters, digits, `_` and `-` by most models, so `time.now` is
+/// offered as `time_now`.
+fn tool_name(command: &str) -> String {
+    command
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn describe_tools(commands: &[CommandDescriptor]) -> String {
+    commands
+        .iter()
+        .map(|command| format!("- {}: {}", tool_name(&command.command), command.description))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn describe_history(history: &[ChatMessage]) -> String {
+    history
+        .iter()
+        .map(|message| {
+            let speaker = if message.role == Role::User {
+                "User"
+            } else {
+                "Assistant"
+            };
+            format!("{speaker}: {}", message.content)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn tool(descriptor: &CommandDescriptor) -> Tool {
+    Tool::function(
+        tool_name(&descriptor.command),
+        descriptor.description.clone(),
+        descriptor.parameters.clone(),
+    )
+}
+
+/// Runs one tool call. Errors are not fatal: they are reported to the model, which can explain them
+/// to the user.
+async fn call_tool(
+    actions: &Actions<'_>,
+    commands: &[CommandDescriptor],
+    name: &str,
+    arguments: serde_json::Value,
+) -> Result<CommandResponse, Error> {
+    let command = commands
+        .iter()
+        .find(|descriptor| descriptor.command == name || tool_name(&descriptor.command) == name)
+        .ok_or_else(|| format!("unknown tool {name}"))?;
+    let request = execute_request(&command.command, arguments)?;
+    info!("executing {}", command.command);
+    actions.execute(&request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tool_names_avoid_dots() {
+        assert_eq!(tool_name("time.now"), "time_now");
+        assert_eq!(tool_name("lights-on"), "lights-on");
+    }
+}
@@ -0,0 +1,405 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::BackendConfig;
+use crate::mock::MockBackend;
+use crate::ollama::OllamaBackend;
+use crate::openai::OpenAiBackend;
+
+/// Reply to one [`Backend::chat`] call.
+pub(crate) type ChatFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<ChatMessage, BackendError>> + 'a>>;
+
+/// A chat model that can call tools.
+pub(crate) trait Backend: Send + Sync {
+    /// Sends the conversation and returns the model's reply. `on_content` gets each piece of the
+    /// reply's text as it is generated.
+    fn chat<'a>(
+        &'a self,
+        request: ChatRequest<'a>,
+        on_content: &'a mut dyn FnMut(&str),
+    ) -> ChatFuture<'a>;
+}
+
+/// What is sent to the model for one reply.
+#[derive(Clone, Copy)]
+pub(crate) struct ChatRequest<'a> {
+    pub(crate) messages: &'a [ChatMessage],
+    pub(crate) tools: &'a [Tool],
+    pub(crate) options: &'a GenerationOptions,
+    /// JSON Schema the reply's content has to match, for structured output.
+    pub(crate) format: Option<&'a Value>,
+}
+
+/// Sampling settings. Unset fields leave the model's own defaults in place; backends skip settings
+/// they do not support.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct GenerationOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) top_p: Option<f32>,
+    /// Context window in tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) num_ctx: Option<u32>,
+    /// Most tokens to generate per reply.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) num_predict: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) seed: Option<i64>,
+    /// Sequences that end the reply.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) stop: Option<Vec<String>>,
+    /// How long Ollama keeps the model loaded after the reply: seconds, or a duration like `30m`.
+    /// Overrides `OLLAMA_KEEP_ALIVE`; Ollama takes it next to the options, not among them.
+    #[serde(default, skip_serializing)]
+    pub(crate) keep_alive: Option<Value>,
+}
+
+/// Creates the backend selected by `LLM_BACKEND`.
+pub(crate) fn from_config(config: &BackendConfig, client: &reqwest::Client) -> Box<dyn Backend> {
+    match config {
+        BackendConfig::Ollama {
+            base_url,
+            model,
+            keep_alive,
+        } => Box::new(OllamaBackend::new(
+            client.clone(),
+            base_url,
+            model,
+            keep_alive.clone(),
+        )),
+        BackendConfig::OpenAi {
+            base_url,
+            model,
+            api_key,
+        } => Box::new(OpenAiBackend::new(
+            client.clone(),
+            base_url,
+            model,
+            api_key.clone(),
+        )),
+        BackendConfig::Mock => Box::new(MockBackend),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Role {
+    System,
+    User,
+    Assistant,
+    Tool,
+}
+
+/// A message of the conversation. Serializes as an Ollama `/api/chat` message.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct ChatMessage {
+    pub(crate) role: Role,
+    #[serde(default)]
+    pub(crate) content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) tool_calls: Vec<ToolCall>,
+    /// Set on tool results: the tool that produced the content.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) tool_name: Option<String>,
+    /// Set on tool results: the [`ToolCall::id`] they answer, for backends that identify calls.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) tool_call_id: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct ToolCall {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) id: Option<String>,
+    pub(crate) function: FunctionCall,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct FunctionCall {
+    pub(crate) name: String,
+    #[serde(default)]
+    pub(crate) arguments: Value,
+}
+
+/// A function the model may call, in the shape of the `tools` field.
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct Tool {
+    #[serde(rename = "type")]
+    pub(crate) kind: &'static str,
+    pub(crate) function: FunctionDefinition,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct FunctionDefinition {
+    pub(crate) name: String,
+    pub(crate) description: String,
+    /// JSON Schema of the arguments.
+    pub(crate) parameters: Value,
+}
+
+#[derive(Debug)]
+pub(crate) enum BackendError {
+    /// The backend could not be reached.
+    Unavailable(reqwest::Error),
+    Timeout,
+    /// The backend answered with an error status, e.g. 404 for an unknown model.
+    Status {
+        status: u16,
+        message: String,
+    },
+    /// Generation failed after the reply started streaming.
+    Failed(String),
+    /// The reply stream could not be parsed or ended early.
+    InvalidResponse(String),
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unavailable(err) => write!(f, "model backend is unavailable: {err}"),
+            Self::Timeout => write!(f, "model backend timed out"),
+            Self::Status { status, message } => {
+                write!(f, "model backend returned {status}: {message}")
+            }
+            Self::Failed(message) => write!(f, "model backend failed: {message}"),
+            Self::InvalidResponse(message) => {
+                write!(f, "invalid model backend response: {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BackendError {}
+
+impl BackendError {
+    /// Whether the backend refused the request because the model cannot call tools, e.g. Ollama's
+    /// `400` "... does not support tools".
+    pub(crate) fn rejects_tools(&self) -> bool {
+        match self {
+            Self::Status { status, message } => {
+                *status == 400 && message.to_lowercase().contains("does not support tools")
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<reqwest::Error> for BackendError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            Self::Timeout
+        } else {
+            Self::Unavailable(err)
+        }
+    }
+}
+
+impl GenerationOptions {
+    /// Returns these options with every field `overrides` sets replaced.
+    pub(crate) fn merge(&self, overrides: &Self) -> Self {
+        Self {
+            temperature: overrides.temperature.or(self.temperature),
+            top_p: overrides.top_p.or(self.top_p),
+            num_ctx: overrides.num_ctx.or(self.num_ctx),
+            num_predict: overrides.num_predict.or(self.num_predict),
+            seed: overrides.seed.or(self.seed),
+            stop: overrides.stop.clone().or_else(|| self.stop.clone()),
+            keep_alive: overrides
+                .keep_alive
+                .clone()
+                .or_else(|| self.keep_alive.clone()),
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self
+            .temperature
+            .is_some_and(|temperature| !(0.0..=2.0).contains(&temperature))
+        {
+            return Err("temperature must be between 0 and 2".to_string());
+        }
+        if self
+            .top_p
+            .is_some_and(|top_p| !(top_p > 0.0 && top_p <= 1.0))
+        {
+            return Err("top_p must be greater than 0 and at most 1".to_string());
+        }
+        if self.num_ctx == Some(0) {
+            return Err("num_ctx must be greater than 0".to_string());
+        }
+        if self.num_predict == Some(0) {
+            return Err("num_predict must be greater than 0".to_string());
+        }
+        if self.stop.iter().flatten().any(String::is_empty) {
+            return Err("stop sequences must not be empty".to_string());
+        }
+        match &self.keep_alive {
+            None | Some(Value::Number(_)) => {}
+            Some(Value::String(duration)) if !duration.trim().is_empty() => {}
+            Some(_) => {
+                return Err("keep_alive must be a number of seconds or a duration".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ChatMessage {
+    pub(crate) fn new(role: Role, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+            tool_calls: Vec::new(),
+            tool_name: None,
+            tool_call_id: None,
+        }
+    }
+
+    pub(crate) fn tool_result(call: &ToolCall, content: String) -> Self {
+        Self {
+            tool_name: Some(call.function.name.clone()),
+            tool_call_id: call.id.clone(),
+            ..Self::new(Role::Tool, content)
+        }
+    }
+}
+
+impl Tool {
+    pub(crate) fn function(name: String, description: String, parameters: Value) -> Self {
+        Self {
+            kind: "function",
+            function: FunctionDefinition {
+                name,
+                description,
+                parameters,
+            },
+        }
+    }
+}
+
+/// Turns an error status into [`BackendError::Status`], with the message from the usual `{"error":
+/// "..."}` or `{"error": {"message": "..."}}` bodies.
+pub(crate) async fn check_status(
+    response: reqwest::Response,
+) -> Result<reqwest::Response, BackendError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    let message = serde_json::from_str::<Value>(&body)
+        .ok()
+        .and_then(|body| {
+            let error = body.get("error")?;
+            error
+                .as_str()
+                .or_else(|| error.get("message")?.as_str())
+                .map(str::to_string)
+        })
+        .unwrap_or(body);
+    Err(BackendError::Status {
+        status: status.as_u16(),
+        message,
+    })
+}
+
+/// Feeds each line of a streamed reply to `on_line` until it returns `true` for the last one.
+pub(crate) async fn read_lines(
+    mut response: reqwest::Response,
+    mut on_line: impl FnMut(&[u8]) -> Result<bool, BackendError>,
+) -> Result<(), BackendError> {
+    let mut buffer = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        buffer.extend_from_slice(&chunk);
+        while let Some(end) = buffer.iter().position(|&byte| byte == b'\n') {
+            let line = buffer.drain(..=end).collect::<Vec<_>>();
+            if on_line(line.trim_ascii())? {
+                return Ok(());
+            }
+        }
+    }
+    if on_line(buffer.trim_ascii())? {
+        return Ok(());
+    }
+    Err(BackendError::InvalidResponse(
+        "reply ended before it was done".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_set_fields_only() {
+        let defaults = GenerationOptions {
+            temperature: Some(0.1),
+            num_predict: Some(64),
+            stop: Some(vec!["User:".to_string()]),
+            ..GenerationOptions::default()
+        };
+        let overrides = GenerationOptions {
+            temperature: Some(0.7),
+            seed: Some(42),
+            ..GenerationOptions::default()
+        };
+
+        assert_eq!(
+            defaults.merge(&overrides),
+            GenerationOptions {
+                temperature: Some(0.7),
+                num_predict: Some(64),
+                seed: Some(42),
+                stop: Some(vec!["User:".to_string()]),
+                ..GenerationOptions::default()
+            }
+        );
+        assert_eq!(defaults.merge(&GenerationOptions::default()), defaults);
+    }
+
+    #[test]
+    fn validates_ranges() {
+        assert!(GenerationOptions::default().validate().is_ok());
+        for invalid in [
+            GenerationOptions {
+                temperature: Some(-0.5),
+                ..GenerationOptions::default()
+            },
+            GenerationOptions {
+                top_p: Some(0.0),
+                ..GenerationOptions::default()
+            },
+            GenerationOptions {
+                num_ctx: Some(0),
+                ..GenerationOptions::default()
+            },
+            GenerationOptions {
+                stop: Some(vec![String::new()]),
+                ..GenerationOptions::default()
+            },
+        ] {
+            assert!(invalid.validate().is_err(), "{invalid:?}");
+        }
+    }
+    #[test]
+    fn recognizes_tool_refusals_only() {
+        let status = |status, message: &str| BackendError::Status {
+            status,
+            message: message.to_string(),
+        };
+        assert!(status(
+            400,
+            "registry.ollama.ai/library/gemma3n:latest does not support tools"
+        )
+        .rejects_tools());
+        assert!(!status(400, "invalid tool call arguments").rejects_tools());
+        assert!(!status(400, "tools[0].function.parameters must be an object").rejects_tools());
+        assert!(!status(500, "model does not support tools").rejects_tools());
+    }
+}
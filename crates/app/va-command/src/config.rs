@@ -1,45 +1,219 @@
 use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 
+use serde_json::Value;
+
+use crate::backend::GenerationOptions;
 use crate::error::Error;
+use crate::fast_path::FastPath;
+use crate::intent::OutputFormat;
+use crate::prompt::{Template, DEFAULT_TEMPLATE};
+use crate::sessions::SessionLimits;
 
 const ENV_BIND_ADDR: &str = "BIND_ADDR";
+const ENV_LLM_BACKEND: &str = "LLM_BACKEND";
 const ENV_OLLAMA_BASE_URL: &str = "OLLAMA_BASE_URL";
 const ENV_OLLAMA_MODEL: &str = "OLLAMA_MODEL";
+const ENV_OLLAMA_KEEP_ALIVE: &str = "OLLAMA_KEEP_ALIVE";
+const ENV_OPENAI_BASE_URL: &str = "OPENAI_BASE_URL";
+const ENV_OPENAI_MODEL: &str = "OPENAI_MODEL";
+const ENV_OPENAI_API_KEY: &str = "OPENAI_API_KEY";
+const ENV_LLM_TEMPERATURE: &str = "LLM_TEMPERATURE";
+const ENV_LLM_TOP_P: &str = "LLM_TOP_P";
+const ENV_LLM_NUM_CTX: &str = "LLM_NUM_CTX";
+const ENV_LLM_NUM_PREDICT: &str = "LLM_NUM_PREDICT";
+const ENV_LLM_SEED: &str = "LLM_SEED";
+const ENV_LLM_STOP: &str = "LLM_STOP";
+const ENV_LLM_TIMEOUT_MS: &str = "LLM_TIMEOUT_MS";
+const ENV_OUTPUT_FORMAT: &str = "OUTPUT_FORMAT";
+const ENV_VA_ACTIONS_URL: &str = "VA_ACTIONS_URL";
+const ENV_VA_ACTIONS_COMMANDS_TTL_MS: &str = "VA_ACTIONS_COMMANDS_TTL_MS";
+const ENV_VA_ACTIONS_TIMEOUT_MS: &str = "VA_ACTIONS_TIMEOUT_MS";
+const ENV_SESSION_MAX_TURNS: &str = "SESSION_MAX_TURNS";
+const ENV_SESSION_MAX_TOKENS: &str = "SESSION_MAX_TOKENS";
+const ENV_SESSION_TTL_MS: &str = "SESSION_TTL_MS";
+const ENV_PROMPT_FILE: &str = "PROMPT_FILE";
+const ENV_PROMPT_WATCH_INTERVAL_MS: &str = "PROMPT_WATCH_INTERVAL_MS";
+const ENV_LOCALE: &str = "LOCALE";
+const ENV_FAST_PATH_ENABLED: &str = "FAST_PATH_ENABLED";
+const ENV_FAST_PATH_FILE: &str = "FAST_PATH_FILE";
 
 const DEFAULT_BIND_ADDR: &str = "127.0.0.1:8092";
 const DEFAULT_OLLAMA_BASE_URL: &str = "http://localhost:11434";
 const DEFAULT_OLLAMA_MODEL: &str = "gemma3n";
+const DEFAULT_OPENAI_BASE_URL: &str = "http://localhost:8080/v1";
+const DEFAULT_VA_ACTIONS_URL: &str = "http://127.0.0.1:8093";
+const DEFAULT_LOCALE: &str = "en-US";
+
+/// The model server answering commands, selected by `LLM_BACKEND`.
+#[derive(Clone, Debug)]
+pub(crate) enum BackendConfig {
+    Ollama {
+        base_url: String,
+        model: String,
+        /// How long Ollama keeps the model loaded after a reply: seconds, or a duration string such
+        /// as `30m`.
+        keep_alive: Option<Value>,
+    },
+    /// Any server with an OpenAI-compatible `/chat/completions` endpoint.
+    OpenAi {
+        base_url: String,
+        model: String,
+        api_key: Option<String>,
+    },
+    /// The built-in deterministic model, for tests and trying out the pipeline.
+    Mock,
+}
 
 #[derive(Clone)]
 pub(crate) struct Config {
     pub(crate) bind_addr: String,
-    pub(crate) ollama_base_url: String,
-    pub(crate) ollama_model: String,
+    pub(crate) backend: BackendConfig,
+    /// Defaults for every request; a request's `options` override them field by field.
+    pub(crate) generation: GenerationOptions,
+    /// Limit on a whole model request, including the streamed answer.
+    pub(crate) llm_timeout: Duration,
+    /// Default for requests without a `format`.
+    pub(crate) output_format: OutputFormat,
+    pub(crate) actions_base_url: String,
+    /// How long va-actions' command list is reused before it is fetched again.
+    pub(crate) commands_ttl: Duration,
+    /// Limit on each request to va-actions.
+    pub(crate) actions_timeout: Duration,
+    pub(crate) session_limits: SessionLimits,
+    pub(crate) prompt_file: Option<PathBuf>,
+    pub(crate) prompt_watch_interval: Duration,
+    /// The template from `PROMPT_FILE` as loaded at startup, or the built-in one.
+    pub(crate) prompt: Template,
+    pub(crate) locale: String,
+    /// Phrases from `FAST_PATH_FILE`. Empty without the file or when `FAST_PATH_ENABLED=false`.
+    pub(crate) fast_path: FastPath,
 }
 
 impl Config {
     pub(crate) fn from_env() -> Result<Self, Error> {
         let bind_addr = env::var(ENV_BIND_ADDR).unwrap_or_else(|_| DEFAULT_BIND_ADDR.to_string());
-        let ollama_base_url = env::var(ENV_OLLAMA_BASE_URL)
-            .unwrap_or_else(|_| DEFAULT_OLLAMA_BASE_URL.to_string())
-            .trim()
-            .to_string();
-        if ollama_base_url.is_empty() {
-            return Err(format!("{ENV_OLLAMA_BASE_URL} must not be empty").into());
-        }
+        let backend = match env::var(ENV_LLM_BACKEND).as_deref().map(str::trim) {
+            Err(_) | Ok("ollama") => BackendConfig::Ollama {
+                base_url: non_empty_env(ENV_OLLAMA_BASE_URL, Some(DEFAULT_OLLAMA_BASE_URL))?,
+                model: non_empty_env(ENV_OLLAMA_MODEL, Some(DEFAULT_OLLAMA_MODEL))?,
+                keep_alive: env::var(ENV_OLLAMA_KEEP_ALIVE)
+                    .ok()
+                    .map(|value| value.trim().to_string())
+                    .filter(|value| !value.is_empty())
+                    .map(|value| match value.parse::<i64>() {
+                        Ok(seconds) => Value::from(seconds),
+                        Err(_) => Value::String(value),
+                    }),
+            },
+            Ok("openai") => BackendConfig::OpenAi {
+                base_url: non_empty_env(ENV_OPENAI_BASE_URL, Some(DEFAULT_OPENAI_BASE_URL))?,
+                model: non_empty_env(ENV_OPENAI_MODEL, None)?,
+                api_key: env::var(ENV_OPENAI_API_KEY)
+                    .ok()
+                    .map(|key| key.trim().to_string())
+                    .filter(|key| !key.is_empty()),
+            },
+            Ok("mock") => BackendConfig::Mock,
+            Ok(other) => {
+                return Err(format!(
+                    "{ENV_LLM_BACKEND} must be ollama, openai or mock, got {other}"
+                )
+                .into());
+            }
+        };
 
-        let ollama_model = env::var(ENV_OLLAMA_MODEL)
-            .unwrap_or_else(|_| DEFAULT_OLLAMA_MODEL.to_string())
+        let generation = GenerationOptions {
+            temperature: parse_optional_env(ENV_LLM_TEMPERATURE)?,
+            top_p: parse_optional_env(ENV_LLM_TOP_P)?,
+            num_ctx: parse_optional_env(ENV_LLM_NUM_CTX)?,
+            num_predict: parse_optional_env(ENV_LLM_NUM_PREDICT)?,
+            seed: parse_optional_env(ENV_LLM_SEED)?,
+            stop: match env::var(ENV_LLM_STOP) {
+                Ok(value) => Some(serde_json::from_str(&value).map_err(|_| {
+                    format!("{ENV_LLM_STOP} must be a JSON array of strings: {value}")
+                })?),
+                Err(_) => None,
+            },
+            keep_alive: None,
+        };
+        generation
+            .validate()
+            .map_err(|err| format!("invalid generation options: {err}"))?;
+
+        let actions_base_url = non_empty_env(ENV_VA_ACTIONS_URL, Some(DEFAULT_VA_ACTIONS_URL))?;
+
+        let prompt_file = env::var(ENV_PROMPT_FILE).ok().map(PathBuf::from);
+        let prompt = match &prompt_file {
+            Some(path) => Template::load(path)?,
+            None => Template::parse(DEFAULT_TEMPLATE)?,
+        };
+
+        let locale = env::var(ENV_LOCALE)
+            .unwrap_or_else(|_| DEFAULT_LOCALE.to_string())
             .trim()
             .to_string();
-        if ollama_model.is_empty() {
-            return Err(format!("{ENV_OLLAMA_MODEL} must not be empty").into());
-        }
+
+        // Without `FAST_PATH_FILE` every command goes to the model.
+        let fast_path = match env::var(ENV_FAST_PATH_FILE) {
+            Ok(path) if parse_env(ENV_FAST_PATH_ENABLED, true)? => {
+                FastPath::load(path.trim().as_ref())?
+            }
+            _ => FastPath::default(),
+        };
 
         Ok(Self {
             bind_addr,
-            ollama_base_url,
-            ollama_model,
+            backend,
+            generation,
+            llm_timeout: Duration::from_millis(parse_env(ENV_LLM_TIMEOUT_MS, 120_000)?),
+            output_format: parse_env(ENV_OUTPUT_FORMAT, OutputFormat::Text)?,
+            actions_base_url,
+            commands_ttl: Duration::from_millis(parse_env(ENV_VA_ACTIONS_COMMANDS_TTL_MS, 60_000)?),
+            actions_timeout: Duration::from_millis(parse_env(ENV_VA_ACTIONS_TIMEOUT_MS, 10_000)?),
+            session_limits: SessionLimits {
+                max_turns: parse_env(ENV_SESSION_MAX_TURNS, 10)?,
+                max_tokens: parse_env(ENV_SESSION_MAX_TOKENS, 2_000)?,
+                ttl: Duration::from_millis(parse_env(ENV_SESSION_TTL_MS, 600_000)?),
+            },
+            prompt_file,
+            prompt_watch_interval: Duration::from_millis(parse_env(
+                ENV_PROMPT_WATCH_INTERVAL_MS,
+                2_000,
+            )?),
+            prompt,
+            locale,
+            fast_path,
         })
     }
 }
+
+/// Reads a trimmed value that must not be empty. Without a default the variable is required.
+fn non_empty_env(name: &str, default: Option<&str>) -> Result<String, Error> {
+    let value = match (env::var(name), default) {
+        (Ok(value), _) => value.trim().to_string(),
+        (Err(_), Some(default)) => default.to_string(),
+        (Err(_), None) => return Err(format!("{name} is not set").into()),
+    };
+    if value.is_empty() {
+        return Err(format!("{name} must not be empty").into());
+    }
+    Ok(value)
+}
+
+fn parse_env<T: FromStr>(name: &str, default: T) -> Result<T, Error> {
+    Ok(parse_optional_env(name)?.unwrap_or(default))
+}
+
+fn parse_optional_env<T: FromStr>(name: &str) -> Result<Option<T>, Error> {
+    match env::var(name) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("{name} has an invalid value: {value}").into()),
+        Err(_) => Ok(None),
+    }
+}
@@ -0,0 +1,331 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::Value;
+use va_skills::{CommandRequest, CommandResponse, ExecuteRequest};
+
+use crate::actions::execute_request;
+use crate::error::Error;
+
+/// The example rules file, parsed by the tests so it stays valid.
+#[cfg(test)]
+pub(crate) const EXAMPLE_RULES: &str = include_str!("../fast_path.example.toml");
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default)]
+    commands: Vec<CommandEntry>,
+}
+
+/// `[[commands]]` entry of the rules file: phrases and replies by language.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CommandEntry {
+    command: String,
+    phrases: BTreeMap<String, Vec<String>>,
+    replies: BTreeMap<String, String>,
+}
+
+/// Phrases that run a va-actions command directly instead of asking the model, checked when they
+/// are loaded so a match always has a request and a reply.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FastPath {
+    rules: Vec<Rule>,
+    /// Normalized phrase to the index of its rule and its language.
+    phrases: HashMap<String, (usize, String)>,
+}
+
+#[derive(Clone, Debug)]
+struct Rule {
+    command: String,
+    request: ExecuteRequest,
+    replies: BTreeMap<String, Reply>,
+}
+
+/// A reply with `{placeholder}`s for the command's result.
+#[derive(Clone, Debug)]
+struct Reply {
+    segments: Vec<Segment>,
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Text(String),
+    Placeholder(String),
+}
+
+/// The rule a command matched and the language of the phrase.
+pub(crate) struct FastPathMatch<'a> {
+    rule: &'a Rule,
+    reply: &'a Reply,
+}
+
+impl FastPath {
+    pub(crate) fn load(path: &Path) -> Result<Self, Error> {
+        let source = fs::read_to_string(path)
+            .map_err(|err| format!("failed to read fast path file {}: {err}", path.display()))?;
+        Self::parse(&source)
+            .map_err(|err| format!("invalid fast path file {}: {err}", path.display()).into())
+    }
+
+    pub(crate) fn parse(source: &str) -> Result<Self, Error> {
+        let file: RulesFile = toml::from_str(source)?;
+        let mut fast_path = Self::default();
+        for entry in file.commands {
+            let command = entry.command.trim().to_string();
+            let request = execute_request(&command, Value::Null)?;
+            let names = placeholders(&request.command);
+
+            let mut replies = BTreeMap::new();
+            for (language, reply) in entry.replies {
+                let reply = Reply::parse(&reply, names)
+                    .map_err(|err| format!("{command}: reply for {language}: {err}"))?;
+                replies.insert(language, reply);
+            }
+
+            let index = fast_path.rules.len();
+            for (language, phrases) in entry.phrases {
+                if !replies.contains_key(&language) {
+                    return Err(format!("{command}: phrases for {language} have no reply").into());
+                }
+                for phrase in phrases {
+                    let normalized = normalize(&phrase);
+                    if normalized.is_empty() {
+                        return Err(format!("{command}: empty phrase for {language}").into());
+                    }
+                    if fast_path
+                        .phrases
+                        .insert(normalized, (index, language.clone()))
+                        .is_some()
+                    {
+                        return Err(format!(
+                            "{command}: phrase \"{phrase}\" is used more than once"
+                        )
+                        .into());
+                    }
+                }
+            }
+
+            fast_path.rules.push(Rule {
+                command,
+                request,
+                replies,
+            });
+        }
+        Ok(fast_path)
+    }
+
+    /// Returns the rule with a phrase equal to the whole command.
+    pub(crate) fn find(&self, command: &str) -> Option<FastPathMatch<'_>> {
+        let (index, language) = self.phrases.get(&normalize(command))?;
+        let rule = &self.rules[*index];
+        Some(FastPathMatch {
+            rule,
+            reply: &rule.replies[language],
+        })
+    }
+}
+
+impl FastPathMatch<'_> {
+    /// The va-actions command, e.g. `time.now`.
+    pub(crate) fn command(&self) -> &str {
+        &self.rule.command
+    }
+
+    pub(crate) fn request(&self) -> &ExecuteRequest {
+        &self.rule.request
+    }
+
+    /// The spoken reply for the command's result, or `None` if va-actions answered with the result
+    /// of another command.
+    pub(crate) fn reply(&self, response: &CommandResponse) -> Option<String> {
+        let values = values(response);
+        self.reply
+            .segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => Some(text.as_str()),
+                Segment::Placeholder(name) => values
+                    .iter()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.as_str()),
+            })
+            .collect()
+    }
+}
+
+impl Reply {
+    fn parse(source: &str, names: &[&str]) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut rest = source.trim();
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_string()));
+            }
+            let after = &rest[start + 1..];
+            let end = after.find('}').ok_or("unclosed {")?;
+            let name = after[..end].trim();
+            if !names.contains(&name) {
+                return Err(format!(
+                    "unknown placeholder {{{name}}}, expected one of {}",
+                    names.join(", ")
+                ));
+            }
+            segments.push(Segment::Placeholder(name.to_string()));
+            rest = &after[end + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+        if segments.is_empty() {
+            return Err("reply must not be empty".to_string());
+        }
+        Ok(Self { segments })
+    }
+}
+
+/// Placeholders a reply for the command can use, matching [`values`].
+fn placeholders(request: &CommandRequest) -> &'static [&'static str] {
+    match request {
+        CommandRequest::TimeNow => &["time", "hour", "minute"],
+        CommandRequest::DateNow => &["date", "year", "month", "day"],
+    }
+}
+
+/// A result as it reads aloud: `10:30` rather than `10:30:00`, `9` rather than `09`.
+fn values(response: &CommandResponse) -> Vec<(&'static str, String)> {
+    match response {
+        CommandResponse::TimeNow(result) => {
+            let mut parts = result.time.split(':');
+            let hour = parts.next().unwrap_or_default();
+            let minute = parts.next().unwrap_or("00");
+            vec![
+                ("time", format!("{hour}:{minute}")),
+                ("hour", without_leading_zero(hour)),
+                ("minute", minute.to_string()),
+            ]
+        }
+        CommandResponse::DateNow(result) => {
+            let mut parts = result.date.split('-');
+            vec![
+                ("date", result.date.clone()),
+                ("year", parts.next().unwrap_or_default().to_string()),
+                (
+                    "month",
+                    without_leading_zero(parts.next().unwrap_or_default()),
+                ),
+                (
+                    "day",
+                    without_leading_zero(parts.next().unwrap_or_default()),
+                ),
+            ]
+        }
+    }
+}
+
+fn without_leading_zero(number: &str) -> String {
+    match number.trim_start_matches('0') {
+        "" => number.chars().take(1).collect(),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+/// Lowercases and drops punctuation, so "What's the time?" matches the phrase "whats the time".
+fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .filter(|c| !matches!(c, '\'' | '’'))
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use va_skills::{DateNowResult, TimeNowResult};
+
+    fn time(time: &str) -> CommandResponse {
+        CommandResponse::TimeNow(TimeNowResult {
+            time: time.to_string(),
+            rfc3339: String::new(),
+        })
+    }
+
+    #[test]
+    fn matches_phrases_in_any_language() {
+        let fast_path = FastPath::parse(EXAMPLE_RULES).unwrap();
+
+        let matched = fast_path.find("What's the time?").unwrap();
+        assert_eq!(matched.command(), "time.now");
+        assert!(matches!(matched.request().command, CommandRequest::TimeNow));
+        assert_eq!(matched.reply(&time("09:05:12")).unwrap(), "It is 09:05.");
+
+        let matched = fast_path.find("Wie spät ist es").unwrap();
+        assert_eq!(
+            matched.reply(&time("09:05:12")).unwrap(),
+            "Es ist 9 Uhr 05."
+        );
+
+        let matched = fast_path.find("quelle heure est-il ?").unwrap();
+        assert_eq!(
+            matched.reply(&time("00:30:00")).unwrap(),
+            "Il est 0 heures 30."
+        );
+
+        let date = CommandResponse::DateNow(DateNowResult {
+            date: "2026-10-08".to_string(),
+            rfc3339: String::new(),
+        });
+        let matched = fast_path.find("der Wievielte ist heute?").unwrap();
+        assert_eq!(matched.reply(&date).unwrap(), "Heute ist der 8.10.2026.");
+        let matched = fast_path.find("What's the date?").unwrap();
+        assert_eq!(matched.reply(&date).unwrap(), "Today is 10/8/2026.");
+        assert!(matched.reply(&time("10:30:00")).is_none());
+
+        assert!(fast_path.find("what time is it in Tokyo").is_none());
+        assert!(fast_path.find("time").is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        for (command, phrases, replies) in [
+            (
+                "lights.on",
+                r#"{ en = ["lights on"] }"#,
+                r#"{ en = "On." }"#,
+            ),
+            (
+                "time.now",
+                r#"{ de = ["uhrzeit"] }"#,
+                r#"{ en = "{time}" }"#,
+            ),
+            ("time.now", r#"{ en = ["time"] }"#, r#"{ en = "{date}" }"#),
+            (
+                "time.now",
+                r#"{ en = ["time"] }"#,
+                r#"{ en = "It is {time" }"#,
+            ),
+            (
+                "time.now",
+                r#"{ en = ["time", "Time?"] }"#,
+                r#"{ en = "{time}" }"#,
+            ),
+            ("time.now", r#"{ en = ["?"] }"#, r#"{ en = "{time}" }"#),
+        ] {
+            let source = format!(
+                "[[commands]]\ncommand = \"{command}\"\nphrases = {phrases}\nreplies = {replies}"
+            );
+            assert!(FastPath::parse(&source).is_err(), "{source}");
+        }
+        assert!(FastPath::parse("")
+            .unwrap()
+            .find("what time is it")
+            .is_none());
+    }
+}
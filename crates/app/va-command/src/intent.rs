@@ -0,0 +1,93 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+/// What the model is asked to answer with.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum OutputFormat {
+    /// Free-form text, meant to be spoken.
+    #[default]
+    Text,
+    /// An [`Intent`], enforced with a JSON schema.
+    Intent,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "text" => Ok(Self::Text),
+            "intent" => Ok(Self::Intent),
+            other => Err(format!("unknown output format {other}")),
+        }
+    }
+}
+
+/// A structured answer for consumers that act on commands rather than speak the answer.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Intent {
+    /// What the user wants, e.g. `lights_on`, or `none` for small talk and questions.
+    pub(crate) intent: String,
+    /// The intent's parameters, e.g. `{"room": "kitchen"}`.
+    #[serde(default)]
+    pub(crate) slots: Map<String, Value>,
+    /// What to tell the user.
+    pub(crate) reply: String,
+}
+
+impl Intent {
+    /// JSON Schema the model's reply has to match.
+    pub(crate) fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "intent": { "type": "string" },
+                "slots": { "type": "object" },
+                "reply": { "type": "string" },
+            },
+            "required": ["intent", "slots", "reply"],
+            "additionalProperties": false,
+        })
+    }
+
+    /// Parses a reply generated with [`Intent::schema`]. Backends that do not enforce the schema
+    /// may still send something else, so the error is meant to be shown to the model.
+    pub(crate) fn parse(reply: &str) -> Result<Self, String> {
+        let intent: Self = serde_json::from_str(reply.trim()).map_err(|err| err.to_string())?;
+        if intent.intent.trim().is_empty() {
+            return Err("intent must not be empty".to_string());
+        }
+        Ok(intent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_valid_intents_only() {
+        let intent = Intent::parse(
+            r#" {"intent": "lights_on", "slots": {"room": "kitchen"}, "reply": "Done."} "#,
+        )
+        .unwrap();
+        assert_eq!(intent.intent, "lights_on");
+        assert_eq!(intent.slots["room"], "kitchen");
+        assert_eq!(intent.reply, "Done.");
+
+        assert!(Intent::parse(r#"{"intent": "none", "reply": "Hi."}"#)
+            .unwrap()
+            .slots
+            .is_empty());
+        assert!(Intent::parse("Sure, the lights are on.").is_err());
+        assert!(Intent::parse(r#"{"intent": "", "slots": {}, "reply": "Hi."}"#).is_err());
+        assert!(Intent::parse(
+            r#"{"intent": "none", "slots": {}, "reply": "Hi.", "mood": "happy"}"#
+        )
+        .is_err());
+    }
+}
@@ -1,46 +1,163 @@
+mod actions;
+mod assistant;
+mod backend;
 mod config;
 mod error;
+mod fast_path;
+mod intent;
+mod mock;
+mod ollama;
+mod openai;
+mod prompt;
+mod reload;
+mod requests;
+mod sentences;
+mod sessions;
+mod stream;
+
+use actix_web::http::{header, StatusCode};
+use actix_web::{delete, get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
-use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder};
 use serde::{Deserialize, Serialize};
 use tracing::{info, warn};
 use tracing_subscriber::EnvFilter;
+use va_skills::CommandResponse;
 
+use crate::actions::CommandCache;
+use crate::assistant::AnswerPath;
+use crate::backend::{Backend, GenerationOptions};
 use crate::config::Config;
+use crate::error::Error;
+use crate::intent::{Intent, OutputFormat};
+use crate::reload::PromptStore;
+use crate::requests::Requests;
+use crate::sessions::Sessions;
 
 const MAX_INPUT_LENGTH: usize = 20000;
+const REQUEST_ID_HEADER: &str = "x-request-id";
 
 struct AppState {
     config: Config,
     client: reqwest::Client,
+    sessions: Sessions,
+    prompts: Arc<PromptStore>,
+    backend: Box<dyn Backend>,
+    requests: Requests,
+    commands: CommandCache,
+    /// When the backend last refused tools. Commands are sent without them for `commands_ttl`
+    /// afterwards, then tools are offered again.
+    tools_refused_at: Mutex<Option<Instant>>,
+}
+
+impl AppState {
+    fn new(config: Config) -> Result<Self, Error> {
+        let llm_client = reqwest::Client::builder()
+            .timeout(config.llm_timeout)
+            .build()?;
+        let client = reqwest::Client::builder()
+            .timeout(config.actions_timeout)
+            .build()?;
+        Ok(Self {
+            backend: backend::from_config(&config.backend, &llm_client),
+            sessions: Sessions::new(config.session_limits),
+            prompts: Arc::new(PromptStore::new(
+                config.prompt_file.clone(),
+                config.prompt.clone(),
+            )),
+            requests: Requests::default(),
+            commands: CommandCache::new(config.commands_ttl),
+            tools_refused_at: Mutex::new(None),
+            config,
+            client,
+        })
+    }
 }
 
 #[derive(Deserialize)]
 struct WebhookRequest {
+    #[serde(default)]
+    event: WebhookEvent,
+    #[serde(default)]
     text: String,
+    /// Conversation the command belongs to. Defaults to `source`, so each device keeps its own
+    /// conversation.
+    #[serde(default)]
+    session: Option<String>,
+    /// Sent by va-activator.
+    #[serde(default)]
+    source: Option<String>,
+    /// Id for cancelling the request. Defaults to the `X-Request-Id` header, or a generated id.
+    #[serde(default)]
+    request_id: Option<String>,
+    /// Overrides the configured generation options for this request.
+    #[serde(default)]
+    options: GenerationOptions,
+    /// `intent` asks for a structured [`Intent`] instead of text. Defaults to `OUTPUT_FORMAT`.
+    #[serde(default)]
+    format: Option<OutputFormat>,
+}
+
+impl WebhookRequest {
+    fn session_id(&self) -> Option<&str> {
+        self.session
+            .as_deref()
+            .or(self.source.as_deref())
+            .filter(|id| !id.is_empty())
+    }
+}
+
+#[derive(Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum WebhookEvent {
+    #[default]
+    Command,
+    Cancel,
 }
 
 #[derive(Serialize)]
 struct WebhookResponse {
     status: &'static str,
     message: String,
+    /// Results of the va-actions commands run while answering.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    results: Vec<CommandResponse>,
+    /// The structured answer, for requests with `"format": "intent"`. `message` holds its reply.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    intent: Option<Intent>,
+    /// `fast_path` or `model`, for answers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<AnswerPath>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
 }
 
-#[derive(Serialize)]
-struct HealthResponse {
-    status: &'static str,
+/// Body of `POST /cancel`: one request, or all requests of a source.
+#[derive(Deserialize)]
+struct CancelRequest {
+    #[serde(default)]
+    request_id: Option<String>,
+    #[serde(default)]
+    source: Option<String>,
 }
 
-#[derive(Serialize)]
-struct OllamaRequest<'a> {
-    model: &'a str,
-    prompt: &'a str,
-    stream: bool,
+impl WebhookResponse {
+    fn new(status: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+            results: Vec::new(),
+            intent: None,
+            path: None,
+            request_id: None,
+        }
+    }
 }
 
-#[derive(Deserialize)]
-struct OllamaResponse {
-    response: String,
+#[derive(Serialize)]
+struct HealthResponse {
+    status: &'static str,
 }
 
 #[actix_web::main]
@@ -63,16 +180,32 @@ async fn main() -> std::io::Result<()> {
     let bind_addr = config.bind_addr.clone();
     info!("va-command listening on {bind_addr}");
 
-    let app_state = web::Data::new(AppState {
-        config,
-        client: reqwest::Client::new(),
-    });
+    let app_state = match AppState::new(config) {
+        Ok(state) => web::Data::new(state),
+        Err(err) => {
+            eprintln!("startup error: {err}");
+            std::process::exit(1);
+        }
+    };
+
+    if app_state.config.prompt_file.is_some() {
+        let interval = app_state.config.prompt_watch_interval;
+        if !interval.is_zero() {
+            actix_web::rt::spawn(reload::watch(app_state.prompts.clone(), interval));
+        }
+        #[cfg(unix)]
+        actix_web::rt::spawn(reload::reload_on_sighup(app_state.prompts.clone()));
+    }
 
     HttpServer::new(move || {
         App::new()
             .app_data(app_state.clone())
             .service(health)
             .service(webhook)
+            .service(webhook_stream)
+            .service(reset_session)
+            .service(cancel)
+            .service(cancel_request)
     })
     .bind(bind_addr)?
     .run()
@@ -87,64 +220,1216 @@ async fn health() -> impl Responder {
 #[post("/webhook")]
 async fn webhook(
     state: web::Data<AppState>,
+    request: HttpRequest,
     payload: web::Json<WebhookRequest>,
 ) -> HttpResponse {
+    let command = match command_text(&state, &payload) {
+        Ok(command) => command,
+        Err(response) => return response,
+    };
+    let options = match generation_options(&state, &payload) {
+        Ok(options) => options,
+        Err(response) => return response,
+    };
+
+    let mut inflight = state
+        .requests
+        .start(request_id(&request, &payload), payload.source.as_deref());
+    let format = payload.format.unwrap_or(state.config.output_format);
+    let mut ignore_text = |_: &str| {};
+    let answer = assistant::answer(
+        &state,
+        command,
+        payload.session_id(),
+        &options,
+        format,
+        &mut ignore_text,
+    );
+    let (status, response) = tokio::select! {
+        result = answer => match result {
+            Ok(answer) => (
+                StatusCode::OK,
+                WebhookResponse {
+                    results: answer.results,
+                    intent: answer.intent,
+                    path: Some(answer.path),
+                    ..WebhookResponse::new("ok", answer.text)
+                },
+            ),
+            Err(err) => {
+                warn!("failed to answer command: {err}");
+                (err.http_status(), WebhookResponse::new("error", err.to_string()))
+            }
+        },
+        () = inflight.cancelled() => {
+            info!("request {} cancelled", inflight.id());
+            (StatusCode::OK, WebhookResponse::new("cancelled", "Request cancelled"))
+        }
+    };
+    HttpResponse::build(status)
+        .insert_header((REQUEST_ID_HEADER, inflight.id()))
+        .json(WebhookResponse {
+            request_id: Some(inflight.id().to_string()),
+            ..response
+        })
+}
+
+/// Like `/webhook`, but answers with Server-Sent Events while the model is still generating.
+#[post("/webhook/stream")]
+async fn webhook_stream(
+    state: web::Data<AppState>,
+    request: HttpRequest,
+    payload: web::Json<WebhookRequest>,
+) -> HttpResponse {
+    let command = match command_text(&state, &payload) {
+        Ok(command) => command.to_string(),
+        Err(response) => return response,
+    };
+    let options = match generation_options(&state, &payload) {
+        Ok(options) => options,
+        Err(response) => return response,
+    };
+
+    let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+    let inflight = state
+        .requests
+        .start(request_id(&request, &payload), payload.source.as_deref());
+    let id = inflight.id().to_string();
+    let session = payload.session_id().map(str::to_string);
+    let format = payload.format.unwrap_or(state.config.output_format);
+    actix_web::rt::spawn(stream::generate(
+        state, command, session, options, format, inflight, sender,
+    ));
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .insert_header((REQUEST_ID_HEADER, id))
+        .streaming(stream::body(receiver))
+}
+
+/// Forgets the conversation of a session.
+#[delete("/sessions/{id}")]
+async fn reset_session(state: web::Data<AppState>, id: web::Path<String>) -> HttpResponse {
+    if state.sessions.reset(&id, Instant::now()) {
+        info!("session {id} reset");
+        HttpResponse::NoContent().finish()
+    } else {
+        HttpResponse::NotFound().json(WebhookResponse::new("error", "Unknown session"))
+    }
+}
+
+/// Stops running requests.
+#[post("/cancel")]
+async fn cancel(state: web::Data<AppState>, payload: web::Json<CancelRequest>) -> HttpResponse {
+    match (&payload.request_id, &payload.source) {
+        (Some(id), _) if state.requests.cancel(id) => cancelled_response(1),
+        (Some(_), _) => {
+            HttpResponse::NotFound().json(WebhookResponse::new("error", "Unknown request"))
+        }
+        (None, Some(source)) => cancelled_response(state.requests.cancel_source(Some(source))),
+        (None, None) => HttpResponse::BadRequest().json(WebhookResponse::new(
+            "error",
+            "Missing request_id or source",
+        )),
+    }
+}
+
+#[delete("/requests/{id}")]
+async fn cancel_request(state: web::Data<AppState>, id: web::Path<String>) -> HttpResponse {
+    if state.requests.cancel(&id) {
+        info!("request {id} cancelled");
+        HttpResponse::NoContent().finish()
+    } else {
+        HttpResponse::NotFound().json(WebhookResponse::new("error", "Unknown request"))
+    }
+}
+
+fn cancelled_response(count: usize) -> HttpResponse {
+    if count == 0 {
+        return HttpResponse::Ok().json(WebhookResponse::new("ignored", "Nothing to cancel"));
+    }
+    let plural = if count == 1 { "" } else { "s" };
+    HttpResponse::Ok().json(WebhookResponse::new(
+        "ok",
+        format!("Cancelled {count} request{plural}"),
+    ))
+}
+
+/// The request id from the body or the `X-Request-Id` header, else a new one.
+fn request_id(request: &HttpRequest, payload: &WebhookRequest) -> String {
+    payload
+        .request_id
+        .clone()
+        .or_else(|| {
+            let header = request.headers().get(REQUEST_ID_HEADER)?;
+            header.to_str().ok().map(str::to_string)
+        })
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// Returns the command to answer, or the response for requests that have none. A `cancel` event,
+/// sent by va-activator on a stop word, cancels the requests of its source.
+fn command_text<'a>(
+    state: &AppState,
+    payload: &'a WebhookRequest,
+) -> Result<&'a str, HttpResponse> {
+    if payload.event == WebhookEvent::Cancel {
+        info!("cancel requested");
+        return Err(cancelled_response(
+            state.requests.cancel_source(payload.source.as_deref()),
+        ));
+    }
+
     let command = payload.text.trim();
     if command.is_empty() {
-        return HttpResponse::BadRequest().json(WebhookResponse {
-            status: "error",
-            message: "Missing command text".to_string(),
-        });
+        return Err(
+            HttpResponse::BadRequest().json(WebhookResponse::new("error", "Missing command text"))
+        );
     }
 
     if command.len() > MAX_INPUT_LENGTH {
-        return HttpResponse::BadRequest().json(WebhookResponse {
-            status: "error",
-            message: format!("Command exceeds {MAX_INPUT_LENGTH} characters"),
-        });
+        let message = format!("Command exceeds {MAX_INPUT_LENGTH} characters");
+        return Err(HttpResponse::BadRequest().json(WebhookResponse::new("error", message)));
     }
 
-    let prompt = build_prompt(command);
-    if let Err(err) = send_to_ollama(&state, &prompt).await {
-        warn!("ollama error: {err:?}");
-    }
+    Ok(command)
+}
 
-    HttpResponse::Ok().json(WebhookResponse {
-        status: "ok",
-        message: format!("User asked about {command}"),
-    })
+/// The configured generation options with the request's overrides applied.
+fn generation_options(
+    state: &AppState,
+    payload: &WebhookRequest,
+) -> Result<GenerationOptions, HttpResponse> {
+    let options = state.config.generation.merge(&payload.options);
+    options.validate().map_err(|err| {
+        HttpResponse::BadRequest().json(WebhookResponse::new(
+            "error",
+            format!("Invalid options: {err}"),
+        ))
+    })?;
+    Ok(options)
 }
 
-fn build_prompt(command: &str) -> String {
-    format!(
-        "You are a command handler for a voice assistant. \
-Return a short single sentence describing what the user asked about.\n\n\
-Example:\n\
-User: \"turn on the living room lights\"\n\
-Assistant: \"User asked about turning on the living room lights.\"\n\n\
-User: \"{command}\"\n\
-Assistant:",
-    )
-}
-
-async fn send_to_ollama(state: &AppState, prompt: &str) -> Result<(), reqwest::Error> {
-    let base_url = state.config.ollama_base_url.trim_end_matches('/');
-    let url = format!("{base_url}/api/generate");
-    let payload = OllamaRequest {
-        model: &state.config.ollama_model,
-        prompt,
-        stream: false,
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BackendConfig;
+    use crate::fast_path::{FastPath, EXAMPLE_RULES};
+    use crate::prompt::Template;
+    use crate::sessions::SessionLimits;
+    use actix_web::body::MessageBody;
+    use actix_web::test;
+    use serde_json::{json, Value};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    type Received = Arc<Mutex<Vec<Value>>>;
+
+    /// Starts a mock server that records the JSON body of each `POST` to `path` and answers it with
+    /// `reply`, which gets every body received so far, the new one last. `routes` adds the mock's
+    /// other endpoints.
+    async fn start_mock<F, R>(
+        path: &'static str,
+        reply: F,
+        routes: fn(&mut web::ServiceConfig),
+    ) -> (String, Received, actix_web::dev::ServerHandle)
+    where
+        F: Fn(Vec<Value>) -> R + Clone + Send + 'static,
+        R: std::future::Future<Output = HttpResponse> + 'static,
+    {
+        let received = Received::default();
+        let received_clone = received.clone();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = HttpServer::new(move || {
+            let received = received_clone.clone();
+            let reply = reply.clone();
+            App::new().configure(routes).route(
+                path,
+                web::post().to(move |payload: web::Json<Value>| {
+                    let mut received = received.lock().unwrap();
+                    received.push(payload.into_inner());
+                    reply(received.clone())
+                }),
+            )
+        })
+        .shutdown_timeout(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        (format!("http://{addr}"), received, handle)
+    }
+
+    /// A complete, non-streamed `/api/chat` reply.
+    fn chat_response(message: Value) -> HttpResponse {
+        HttpResponse::Ok().json(json!({ "model": "test", "message": message, "done": true }))
+    }
+
+    /// Starts a mock Ollama that records each `/api/chat` request. It asks for `time_now` until the
+    /// conversation holds a tool result and then answers with the text of that result.
+    async fn start_ollama() -> (String, Received, actix_web::dev::ServerHandle) {
+        start_mock(
+            "/api/chat",
+            |chats| async move {
+                let tool_result = chats.last().unwrap()["messages"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .find(|message| message["role"] == "tool")
+                    .map(|message| message["content"].as_str().unwrap().to_string());
+                chat_response(match tool_result {
+                    Some(result) => json!({
+                        "role": "assistant",
+                        "content": format!("Result: {result}"),
+                    }),
+                    None => json!({
+                        "role": "assistant",
+                        "content": "Let me check",
+                        "tool_calls": [{
+                            "function": { "name": "time_now", "arguments": {} },
+                        }],
+                    }),
+                })
+            },
+            |_| {},
+        )
+        .await
+    }
+
+    /// Starts a mock Ollama that does not have the requested model.
+    async fn start_ollama_without_model() -> (String, Received, actix_web::dev::ServerHandle) {
+        start_mock(
+            "/api/chat",
+            |_| async {
+                HttpResponse::NotFound()
+                    .json(json!({ "error": "model \"test\" not found, try pulling it first" }))
+            },
+            |_| {},
+        )
+        .await
+    }
+
+    /// Starts a mock Ollama whose model cannot call tools. It records each `/api/chat` request and
+    /// rejects the ones that offer tools, like Ollama does.
+    async fn start_ollama_without_tools() -> (String, Received, actix_web::dev::ServerHandle) {
+        start_mock(
+            "/api/chat",
+            |chats| async move {
+                if chats.last().unwrap().get("tools").is_some() {
+                    let error = "registry.ollama.ai/library/test:latest does not support tools";
+                    return HttpResponse::BadRequest().json(json!({ "error": error }));
+                }
+                chat_response(json!({ "role": "assistant", "content": "It is late." }))
+            },
+            |_| {},
+        )
+        .await
+    }
+
+    /// Starts a mock Ollama that takes a second to answer.
+    async fn start_slow_ollama() -> (String, Received, actix_web::dev::ServerHandle) {
+        start_mock(
+            "/api/chat",
+            |_| async {
+                actix_web::rt::time::sleep(Duration::from_secs(1)).await;
+                chat_response(json!({ "role": "assistant", "content": "Too late." }))
+            },
+            |_| {},
+        )
+        .await
+    }
+
+    /// Starts a mock Ollama that records each `/api/chat` request and answers the n-th one with
+    /// `replies[n]`, or with the last reply once it runs out.
+    async fn start_ollama_replying(
+        replies: &'static [&'static str],
+    ) -> (String, Received, actix_web::dev::ServerHandle) {
+        start_mock(
+            "/api/chat",
+            move |chats| async move {
+                let content = replies[(chats.len() - 1).min(replies.len() - 1)];
+                chat_response(json!({ "role": "assistant", "content": content }))
+            },
+            |_| {},
+        )
+        .await
+    }
+
+    /// Sets the flag when the mock's reply stream is dropped, i.e. when va-command stopped reading
+    /// it.
+    struct DropFlag(Arc<AtomicBool>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// One NDJSON line of a streamed Ollama reply.
+    fn chat_chunk(content: &str, done: bool) -> web::Bytes {
+        let message = json!({ "role": "assistant", "content": content });
+        web::Bytes::from(format!("{}\n", json!({ "message": message, "done": done })))
+    }
+
+    /// Starts a mock Ollama that streams `pieces` as NDJSON chunks, 10 ms apart. Without `done` it
+    /// keeps repeating the pieces until the reader goes away.
+    async fn start_streaming_ollama(
+        pieces: &'static [&'static str],
+        done: bool,
+    ) -> (String, Arc<AtomicBool>, actix_web::dev::ServerHandle) {
+        let dropped = Arc::new(AtomicBool::new(false));
+        let dropped_clone = dropped.clone();
+        let (url, _chats, handle) = start_mock(
+            "/api/chat",
+            move |_| {
+                let guard = DropFlag(dropped_clone.clone());
+                async move {
+                    let chunks = futures_util::stream::unfold(
+                        (0, guard),
+                        move |(index, guard)| async move {
+                            if done && index > pieces.len() {
+                                return None;
+                            }
+                            actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+                            let line = if done && index == pieces.len() {
+                                chat_chunk("", true)
+                            } else {
+                                chat_chunk(pieces[index % pieces.len()], false)
+                            };
+                            Some((Ok::<_, std::convert::Infallible>(line), (index + 1, guard)))
+                        },
+                    );
+                    HttpResponse::Ok()
+                        .content_type("application/x-ndjson")
+                        .streaming(chunks)
+                }
+            },
+            |_| {},
+        )
+        .await;
+
+        (url, dropped, handle)
+    }
+
+    /// Starts a mock va-actions offering `time.now` and recording each `/execute` request.
+    async fn start_actions() -> (String, Received, actix_web::dev::ServerHandle) {
+        start_mock(
+            "/execute",
+            |_| async {
+                HttpResponse::Ok().json(va_skills::CommandResponse::TimeNow(
+                    va_skills::TimeNowResult {
+                        time: "10:30".to_string(),
+                        rfc3339: "2026-10-18T10:30:00+00:00".to_string(),
+                    },
+                ))
+            },
+            |routes| {
+                routes.route(
+                    "/commands",
+                    web::get().to(|| async {
+                        HttpResponse::Ok().json(va_skills::CommandsResponse {
+                            instructions: "POST /execute".to_string(),
+                            commands: vec![va_skills::CommandDescriptor {
+                                command: va_skills::COMMAND_TIME_NOW.to_string(),
+                                description: "Return the server's current local time.".to_string(),
+                                parameters: va_skills::no_parameters(),
+                                example_request: json!({ "command": va_skills::COMMAND_TIME_NOW }),
+                                example_response: Value::Null,
+                            }],
+                        })
+                    }),
+                );
+            },
+        )
+        .await
+    }
+
+    fn test_config(ollama_base_url: String, actions_base_url: String) -> Config {
+        Config {
+            bind_addr: "127.0.0.1:0".to_string(),
+            backend: BackendConfig::Ollama {
+                base_url: ollama_base_url,
+                model: "test".to_string(),
+                keep_alive: None,
+            },
+            generation: GenerationOptions::default(),
+            llm_timeout: Duration::from_secs(5),
+            output_format: OutputFormat::Text,
+            actions_base_url,
+            session_limits: SessionLimits {
+                max_turns: 10,
+                max_tokens: 2_000,
+                ttl: Duration::from_secs(600),
+            },
+            prompt_file: None,
+            prompt_watch_interval: Duration::ZERO,
+            prompt: Template::parse(prompt::DEFAULT_TEMPLATE).unwrap(),
+            locale: "en-US".to_string(),
+            fast_path: FastPath::default(),
+            commands_ttl: Duration::from_secs(60),
+            actions_timeout: Duration::from_secs(5),
+        }
+    }
+
+    fn test_state(ollama_base_url: String, actions_base_url: String) -> web::Data<AppState> {
+        web::Data::new(AppState::new(test_config(ollama_base_url, actions_base_url)).unwrap())
+    }
+
+    async fn post_command(
+        state: web::Data<AppState>,
+        text: &str,
+    ) -> (actix_web::http::StatusCode, Value) {
+        post_webhook(state, json!({ "text": text })).await
+    }
+
+    async fn post_webhook(
+        state: web::Data<AppState>,
+        payload: Value,
+    ) -> (actix_web::http::StatusCode, Value) {
+        let app = test::init_service(App::new().app_data(state).service(webhook)).await;
+        let req = test::TestRequest::post()
+            .uri("/webhook")
+            .set_json(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let status = resp.status();
+        (status, test::read_body_json(resp).await)
+    }
+
+    #[actix_web::test]
+    async fn executes_tool_calls_through_va_actions() {
+        let (ollama_url, chats, ollama) = start_ollama().await;
+        let (actions_url, executed, actions) = start_actions().await;
+
+        let (status, body) =
+            post_command(test_state(ollama_url, actions_url), "what time is it").await;
+        assert!(status.is_success());
+        assert_eq!(body["status"], "ok");
+        assert!(body["message"].as_str().unwrap().starts_with("Result: "));
+        assert_eq!(
+            body["results"],
+            json!([{
+                "command": "time.now",
+                "result": { "time": "10:30", "rfc3339": "2026-10-18T10:30:00+00:00" },
+            }])
+        );
 
-    let response = state
-        .client
-        .post(url)
-        .json(&payload)
-        .send()
-        .await?
-        .error_for_status()?;
-
-    let body: OllamaResponse = response.json().await?;
-    eprintln!("ollama response: {}", body.response);
-    Ok(())
+        assert_eq!(
+            *executed.lock().unwrap(),
+            [json!({ "command": "time.now" })]
+        );
+
+        let chats = chats.lock().unwrap().clone();
+        assert_eq!(chats.len(), 2);
+        assert_eq!(chats[0]["tools"][0]["function"]["name"], "time_now");
+        assert_eq!(
+            chats[0]["messages"][1],
+            json!({ "role": "user", "content": "what time is it" })
+        );
+        let tool_message = &chats[1]["messages"][3];
+        assert_eq!(tool_message["tool_name"], "time_now");
+        let result: Value =
+            serde_json::from_str(tool_message["content"].as_str().unwrap()).unwrap();
+        assert_eq!(result["result"]["time"], "10:30");
+        assert_eq!(
+            chats[1]["messages"][2]["tool_calls"][0]["function"]["name"],
+            "time_now"
+        );
+
+        ollama.stop(true).await;
+        actions.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn reports_failed_tool_calls_to_the_model() {
+        let (ollama_url, chats, ollama) = start_ollama().await;
+        let unreachable = "http://127.0.0.1:9".to_string();
+
+        let (status, body) =
+            post_command(test_state(ollama_url, unreachable), "what time is it").await;
+        assert!(status.is_success());
+        assert!(body.get("results").is_none());
+
+        let chats = chats.lock().unwrap().clone();
+        assert_eq!(chats.len(), 2);
+        assert!(chats[0].get("tools").is_none());
+        let tool_message = &chats[1]["messages"][3];
+        assert!(tool_message["content"]
+            .as_str()
+            .unwrap()
+            .contains("unknown tool time_now"));
+
+        ollama.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn answers_without_tools_when_the_model_cannot_call_them() {
+        let (actions_url, _executed, actions) = start_actions().await;
+        let (ollama_url, chats, ollama) = start_ollama_without_tools().await;
+        let mut config = test_config(ollama_url, actions_url);
+        config.commands_ttl = Duration::from_millis(500);
+        let state = web::Data::new(AppState::new(config).unwrap());
+        let offered_tools = || {
+            chats
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|chat| chat.get("tools").is_some())
+                .collect::<Vec<_>>()
+        };
+
+        for _ in 0..2 {
+            let (status, body) = post_command(state.clone(), "what time is it").await;
+            assert!(status.is_success());
+            assert_eq!(body["message"], "It is late.");
+        }
+        // Only the first command is tried with tools.
+        assert_eq!(offered_tools(), [true, false, false]);
+
+        // Once the refusal is older than the command list's TTL, tools are offered again.
+        actix_web::rt::time::sleep(Duration::from_millis(600)).await;
+        let (status, _) = post_command(state.clone(), "what time is it").await;
+        assert!(status.is_success());
+        assert_eq!(offered_tools(), [true, false, false, true, false]);
+
+        ollama.stop(true).await;
+        actions.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn maps_ollama_errors_to_http_statuses() {
+        let (actions_url, _executed, actions) = start_actions().await;
+        let (ollama_url, _chats, ollama) = start_ollama_without_model().await;
+
+        let (status, body) = post_command(
+            test_state(ollama_url, actions_url.clone()),
+            "what time is it",
+        )
+        .await;
+        assert_eq!(status, actix_web::http::StatusCode::BAD_GATEWAY);
+        assert_eq!(body["status"], "error");
+        assert!(body["message"]
+            .as_str()
+            .unwrap()
+            .contains("model \"test\" not found"));
+
+        let unreachable = "http://127.0.0.1:9".to_string();
+        let (status, body) =
+            post_command(test_state(unreachable, actions_url), "what time is it").await;
+        assert_eq!(status, actix_web::http::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "error");
+
+        ollama.stop(true).await;
+        actions.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn times_out_slow_models() {
+        let (ollama_url, _chats, ollama) = start_slow_ollama().await;
+        let mut config = test_config(ollama_url, "http://127.0.0.1:9".to_string());
+        config.llm_timeout = Duration::from_millis(100);
+        let state = web::Data::new(AppState::new(config).unwrap());
+
+        let (status, body) = post_command(state, "what time is it").await;
+        assert_eq!(status, actix_web::http::StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(body["status"], "error");
+
+        ollama.stop(true).await;
+    }
+
+    fn stream_request(text: &str) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri("/webhook/stream")
+            .set_json(json!({ "text": text }))
+    }
+
+    /// Reads an SSE body as `(event, data)` pairs.
+    async fn read_events(resp: actix_web::dev::ServiceResponse) -> Vec<(String, Value)> {
+        let body = test::read_body(resp).await;
+        std::str::from_utf8(&body)
+            .unwrap()
+            .split_terminator("\n\n")
+            .map(|frame| {
+                let (event, data) = frame.split_once('\n').unwrap();
+                let data: Value =
+                    serde_json::from_str(data.strip_prefix("data: ").unwrap()).unwrap();
+                (event.strip_prefix("event: ").unwrap().to_string(), data)
+            })
+            .collect()
+    }
+
+    fn sentence_texts(events: &[(String, Value)]) -> Vec<&str> {
+        events
+            .iter()
+            .filter(|(event, _)| event == "sentence")
+            .map(|(_, data)| data["text"].as_str().unwrap())
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn streams_answer_sentence_by_sentence() {
+        let (ollama_url, _dropped, ollama) = start_streaming_ollama(
+            &["Hello there. It", " is 10:30! Is that", " late? Bye"],
+            true,
+        )
+        .await;
+        let state = test_state(ollama_url, "http://127.0.0.1:9".to_string());
+        let app = test::init_service(App::new().app_data(state).service(webhook_stream)).await;
+
+        let resp = test::call_service(&app, stream_request("what time is it").to_request()).await;
+        assert!(resp.status().is_success());
+        assert_eq!(
+            resp.headers().get(header::CONTENT_TYPE).unwrap(),
+            "text/event-stream"
+        );
+
+        let events = read_events(resp).await;
+        let sentences = sentence_texts(&events);
+        assert_eq!(
+            sentences,
+            ["Hello there.", "It is 10:30!", "Is that late?", "Bye"]
+        );
+        let (event, data) = events.last().unwrap();
+        assert_eq!(event, "done");
+        assert_eq!(
+            data["message"],
+            "Hello there. It is 10:30! Is that late? Bye"
+        );
+
+        ollama.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn keeps_text_before_tool_calls_apart_from_the_answer() {
+        let (ollama_url, _chats, ollama) = start_ollama().await;
+        let (actions_url, _executed, actions) = start_actions().await;
+        let state = test_state(ollama_url, actions_url);
+        let app = test::init_service(App::new().app_data(state).service(webhook_stream)).await;
+
+        let resp = test::call_service(&app, stream_request("what time is it").to_request()).await;
+        let events = read_events(resp).await;
+        let sentences = sentence_texts(&events);
+        assert_eq!(sentences.len(), 2, "{sentences:?}");
+        assert_eq!(sentences[0], "Let me check");
+        assert!(sentences[1].starts_with("Result: "));
+        let (event, data) = events.last().unwrap();
+        assert_eq!(event, "done");
+        assert_eq!(data["message"], sentences[1]);
+
+        ollama.stop(true).await;
+        actions.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn stops_generating_when_client_disconnects() {
+        let (ollama_url, dropped, ollama) =
+            start_streaming_ollama(&["Still talking. "], false).await;
+        let state = test_state(ollama_url, "http://127.0.0.1:9".to_string());
+        let app = test::init_service(App::new().app_data(state).service(webhook_stream)).await;
+
+        let resp = test::call_service(&app, stream_request("tell me a story").to_request()).await;
+        let mut body = Box::pin(resp.into_body());
+        let first = std::future::poll_fn(|cx| body.as_mut().poll_next(cx))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(first.starts_with(b"event: sentence\n"));
+        assert!(!dropped.load(Ordering::SeqCst));
+
+        drop(body);
+        for _ in 0..100 {
+            if dropped.load(Ordering::SeqCst) {
+                break;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(
+            dropped.load(Ordering::SeqCst),
+            "ollama stream was not cancelled"
+        );
+
+        ollama.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn remembers_conversation_per_session() {
+        let (ollama_url, chats, ollama) = start_ollama().await;
+        let (actions_url, _executed, actions) = start_actions().await;
+        let state = test_state(ollama_url, actions_url);
+        let user_messages = |chat: &Value| {
+            chat["messages"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter(|message| message["role"] != "system" && message["role"] != "tool")
+                .map(|message| message["role"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        post_webhook(
+            state.clone(),
+            json!({ "text": "what time is it", "source": "kitchen" }),
+        )
+        .await;
+        post_webhook(
+            state.clone(),
+            json!({ "text": "and now", "session": "kitchen" }),
+        )
+        .await;
+        let history = chats.lock().unwrap()[2]["messages"]
+            .as_array()
+            .unwrap()
+            .clone();
+        assert_eq!(
+            history[1],
+            json!({ "role": "user", "content": "what time is it" })
+        );
+        assert_eq!(history[2]["role"], "assistant");
+        assert!(history[2]["content"]
+            .as_str()
+            .unwrap()
+            .starts_with("Result: "));
+        assert_eq!(history[3], json!({ "role": "user", "content": "and now" }));
+
+        post_webhook(
+            state.clone(),
+            json!({ "text": "what time is it", "source": "office" }),
+        )
+        .await;
+        assert_eq!(user_messages(&chats.lock().unwrap()[4]), ["user"]);
+
+        let app =
+            test::init_service(App::new().app_data(state.clone()).service(reset_session)).await;
+        let req = test::TestRequest::delete()
+            .uri("/sessions/kitchen")
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            actix_web::http::StatusCode::NO_CONTENT
+        );
+        let req = test::TestRequest::delete()
+            .uri("/sessions/kitchen")
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            actix_web::http::StatusCode::NOT_FOUND
+        );
+
+        post_webhook(state, json!({ "text": "and now", "source": "kitchen" })).await;
+        assert_eq!(user_messages(&chats.lock().unwrap()[6]), ["user"]);
+
+        ollama.stop(true).await;
+        actions.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn renders_system_prompt_from_template() {
+        let (ollama_url, chats, ollama) = start_ollama().await;
+        let (actions_url, _executed, actions) = start_actions().await;
+        let mut config = test_config(ollama_url, actions_url);
+        config.prompt = Template::parse(
+            "Antworte auf {{locale}}.\nWerkzeuge:\n{{tools}}\nBisher:\n{{history}}",
+        )
+        .unwrap();
+        config.locale = "de-DE".to_string();
+        let state = web::Data::new(AppState::new(config).unwrap());
+
+        post_webhook(
+            state.clone(),
+            json!({ "text": "wie spät ist es", "session": "kitchen" }),
+        )
+        .await;
+        post_webhook(state, json!({ "text": "und jetzt", "session": "kitchen" })).await;
+
+        let chats = chats.lock().unwrap().clone();
+        assert_eq!(
+            chats[0]["messages"][0]["content"],
+            "Antworte auf de-DE.\nWerkzeuge:\n\
+             - time_now: Return the server's current local time.\nBisher:\n"
+        );
+        let messages = chats[2]["messages"].as_array().unwrap();
+        let system_prompt = messages[0]["content"].as_str().unwrap();
+        assert!(
+            system_prompt.contains("Bisher:\nUser: wie spät ist es\nAssistant: Result: "),
+            "{system_prompt}"
+        );
+        // The history is in the system prompt only.
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[1],
+            json!({ "role": "user", "content": "und jetzt" })
+        );
+
+        ollama.stop(true).await;
+        actions.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn answers_with_mock_backend() {
+        let (actions_url, executed, actions) = start_actions().await;
+        let mut config = test_config("http://127.0.0.1:9".to_string(), actions_url);
+        config.backend = BackendConfig::Mock;
+        let state = web::Data::new(AppState::new(config).unwrap());
+
+        let (status, body) = post_command(state.clone(), "What time is it?").await;
+        assert!(status.is_success());
+        assert_eq!(
+            *executed.lock().unwrap(),
+            [json!({ "command": "time.now" })]
+        );
+        assert_eq!(body["results"][0]["command"], "time.now");
+        assert!(
+            body["message"]
+                .as_str()
+                .unwrap()
+                .starts_with("time_now returned {"),
+            "{body}"
+        );
+
+        let (_, body) = post_command(state, "hello there").await;
+        assert_eq!(body["message"], "You said: hello there");
+
+        actions.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn sends_generation_options_with_request_overrides() {
+        let (ollama_url, chats, ollama) = start_ollama().await;
+        let mut config = test_config(ollama_url, "http://127.0.0.1:9".to_string());
+        config.generation = GenerationOptions {
+            temperature: Some(0.1),
+            num_predict: Some(64),
+            ..GenerationOptions::default()
+        };
+        if let BackendConfig::Ollama { keep_alive, .. } = &mut config.backend {
+            *keep_alive = Some(json!("30m"));
+        }
+        let state = web::Data::new(AppState::new(config).unwrap());
+
+        let payload = json!({ "text": "hello", "options": { "temperature": 0.7, "stop": ["\n"] } });
+        let (status, _) = post_webhook(state.clone(), payload).await;
+        assert!(status.is_success());
+        let chat = chats.lock().unwrap()[0].clone();
+        assert_eq!(
+            chat["options"],
+            json!({ "temperature": 0.7, "num_predict": 64, "stop": ["\n"] })
+        );
+        assert_eq!(chat["keep_alive"], "30m");
+
+        let sent = chats.lock().unwrap().len();
+        let payload = json!({ "text": "hello", "options": { "keep_alive": -1 } });
+        let (status, _) = post_webhook(state.clone(), payload).await;
+        assert!(status.is_success());
+        let chat = chats.lock().unwrap()[sent].clone();
+        assert_eq!(chat["keep_alive"], -1);
+        assert_eq!(
+            chat["options"],
+            json!({ "temperature": 0.1, "num_predict": 64 })
+        );
+
+        let (status, body) = post_webhook(
+            state.clone(),
+            json!({ "text": "hello", "options": { "top_p": 2.0 } }),
+        )
+        .await;
+        assert_eq!(status, actix_web::http::StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body["message"],
+            "Invalid options: top_p must be greater than 0 and at most 1"
+        );
+
+        let (status, body) = post_webhook(
+            state.clone(),
+            json!({ "text": "hello", "options": { "keep_alive": true } }),
+        )
+        .await;
+        assert_eq!(status, actix_web::http::StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body["message"],
+            "Invalid options: keep_alive must be a number of seconds or a duration"
+        );
+
+        let app = test::init_service(App::new().app_data(state).service(webhook)).await;
+        let req = test::TestRequest::post()
+            .uri("/webhook")
+            .set_json(json!({ "text": "hello", "options": { "temprature": 0.7 } }))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            actix_web::http::StatusCode::BAD_REQUEST
+        );
+        assert_eq!(chats.lock().unwrap().len(), 4);
+
+        ollama.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn answers_with_intent_after_one_correction() {
+        let (ollama_url, chats, ollama) = start_ollama_replying(&[
+            "Sure, turning on the kitchen lights.",
+            concat!(
+                r#"{"intent": "lights_on", "slots": {"room": "kitchen"}, "#,
+                r#""reply": "The kitchen lights are on."}"#,
+            ),
+        ])
+        .await;
+        let state = test_state(ollama_url, "http://127.0.0.1:9".to_string());
+
+        let payload = json!({ "text": "turn on the kitchen lights", "format": "intent" });
+        let (status, body) = post_webhook(state, payload).await;
+        assert!(status.is_success(), "{body}");
+        assert_eq!(body["message"], "The kitchen lights are on.");
+        assert_eq!(
+            body["intent"],
+            json!({
+                "intent": "lights_on",
+                "slots": { "room": "kitchen" },
+                "reply": "The kitchen lights are on.",
+            })
+        );
+
+        let chats = chats.lock().unwrap().clone();
+        assert_eq!(chats.len(), 2);
+        assert_eq!(chats[0]["format"], Intent::schema());
+        let messages = chats[1]["messages"].as_array().unwrap();
+        assert_eq!(
+            messages[2]["content"],
+            "Sure, turning on the kitchen lights."
+        );
+        assert_eq!(messages[3]["role"], "user");
+        assert!(messages[3]["content"]
+            .as_str()
+            .unwrap()
+            .starts_with("Your reply is not valid"));
+
+        ollama.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn rejects_intents_that_stay_invalid() {
+        let (ollama_url, chats, ollama) =
+            start_ollama_replying(&[r#"{"intent": "lights_on"}"#]).await;
+        let mut config = test_config(ollama_url, "http://127.0.0.1:9".to_string());
+        config.output_format = OutputFormat::Intent;
+        let state = web::Data::new(AppState::new(config).unwrap());
+
+        let (status, body) = post_command(state.clone(), "turn on the lights").await;
+        assert_eq!(status, actix_web::http::StatusCode::BAD_GATEWAY);
+        assert!(
+            body["message"]
+                .as_str()
+                .unwrap()
+                .contains("does not match the intent schema"),
+            "{body}"
+        );
+        assert_eq!(chats.lock().unwrap().len(), 2);
+
+        let (status, body) =
+            post_webhook(state, json!({ "text": "hello", "format": "text" })).await;
+        assert!(status.is_success());
+        assert_eq!(body["message"], r#"{"intent": "lights_on"}"#);
+        assert!(body.get("intent").is_none());
+        assert!(chats.lock().unwrap()[2].get("format").is_none());
+
+        ollama.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn answers_known_phrases_without_the_model() {
+        let (actions_url, executed, actions) = start_actions().await;
+        let (ollama_url, chats, ollama) = start_ollama_replying(&["Hello!"]).await;
+        let mut config = test_config(ollama_url.clone(), actions_url);
+        config.fast_path = FastPath::parse(EXAMPLE_RULES).unwrap();
+        let state = web::Data::new(AppState::new(config.clone()).unwrap());
+
+        let (status, body) = post_command(state.clone(), "What time is it?").await;
+        assert!(status.is_success());
+        assert_eq!(body["path"], "fast_path");
+        assert_eq!(body["message"], "It is 10:30.");
+        assert_eq!(body["results"][0]["command"], "time.now");
+        assert_eq!(
+            *executed.lock().unwrap(),
+            [json!({ "command": "time.now" })]
+        );
+
+        let payload = json!({ "text": "Wie spät ist es", "format": "intent" });
+        let (_, body) = post_webhook(state.clone(), payload).await;
+        assert_eq!(body["message"], "Es ist 10 Uhr 30.");
+        assert_eq!(
+            body["intent"],
+            json!({ "intent": "time_now", "slots": {}, "reply": "Es ist 10 Uhr 30." })
+        );
+        assert!(chats.lock().unwrap().is_empty());
+
+        let (_, body) = post_command(state, "hello").await;
+        assert_eq!(body["path"], "model");
+        assert_eq!(body["message"], "Hello!");
+
+        // Without va-actions the model gets to answer.
+        config.actions_base_url = "http://127.0.0.1:9".to_string();
+        let (status, body) = post_command(
+            web::Data::new(AppState::new(config).unwrap()),
+            "what time is it",
+        )
+        .await;
+        assert!(status.is_success());
+        assert_eq!(body["path"], "model");
+        assert_eq!(chats.lock().unwrap().len(), 2);
+
+        ollama.stop(true).await;
+        actions.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn cancels_running_requests_by_id() {
+        let (ollama_url, _dropped, ollama) =
+            start_streaming_ollama(&["Still talking. "], false).await;
+        let state = test_state(ollama_url, "http://127.0.0.1:9".to_string());
+        let app = test::init_service(
+            App::new()
+                .app_data(state)
+                .service(webhook)
+                .service(cancel)
+                .service(cancel_request),
+        )
+        .await;
+
+        let command = test::TestRequest::post()
+            .uri("/webhook")
+            .set_json(json!({ "text": "tell me a story", "request_id": "r1", "source": "kitchen" }))
+            .to_request();
+        let cancel_when_running = async {
+            for _ in 0..100 {
+                let req = test::TestRequest::delete().uri("/requests/r1").to_request();
+                if test::call_service(&app, req).await.status()
+                    == actix_web::http::StatusCode::NO_CONTENT
+                {
+                    return true;
+                }
+                actix_web::rt::time::sleep(Duration::from_millis(20)).await;
+            }
+            false
+        };
+        let (resp, cancelled) =
+            tokio::join!(test::call_service(&app, command), cancel_when_running);
+        assert!(cancelled);
+        assert!(resp.status().is_success());
+        assert_eq!(resp.headers().get(REQUEST_ID_HEADER).unwrap(), "r1");
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(
+            body,
+            json!({ "status": "cancelled", "message": "Request cancelled", "request_id": "r1" })
+        );
+
+        let req = test::TestRequest::delete().uri("/requests/r1").to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            actix_web::http::StatusCode::NOT_FOUND
+        );
+        let req = test::TestRequest::post()
+            .uri("/cancel")
+            .set_json(json!({ "request_id": "r1" }))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            actix_web::http::StatusCode::NOT_FOUND
+        );
+        let req = test::TestRequest::post()
+            .uri("/cancel")
+            .set_json(json!({}))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            actix_web::http::StatusCode::BAD_REQUEST
+        );
+
+        ollama.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn cancel_event_stops_the_sources_streams() {
+        let (ollama_url, dropped, ollama) =
+            start_streaming_ollama(&["Still talking. "], false).await;
+        let state = test_state(ollama_url, "http://127.0.0.1:9".to_string());
+        let app = test::init_service(
+            App::new()
+                .app_data(state)
+                .service(webhook)
+                .service(webhook_stream)
+                .service(cancel),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/webhook/stream")
+            .set_json(json!({ "text": "tell me a story", "source": "kitchen" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let request_id = resp
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(uuid::Uuid::parse_str(&request_id).is_ok());
+        let mut body = Box::pin(resp.into_body());
+        let first = std::future::poll_fn(|cx| body.as_mut().poll_next(cx))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(first.starts_with(b"event: sentence\n"));
+
+        let req = test::TestRequest::post()
+            .uri("/cancel")
+            .set_json(json!({ "source": "living-room" }))
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["status"], "ignored");
+
+        let req = test::TestRequest::post()
+            .uri("/webhook")
+            .set_json(json!({ "event": "cancel", "source": "kitchen", "request_id": "stop-1" }))
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            resp,
+            json!({ "status": "ok", "message": "Cancelled 1 request" })
+        );
+
+        let mut rest = Vec::new();
+        while let Some(frame) = std::future::poll_fn(|cx| body.as_mut().poll_next(cx)).await {
+            rest.extend_from_slice(&frame.unwrap());
+        }
+        let rest = String::from_utf8(rest).unwrap();
+        let cancelled = rest
+            .split("\n\n")
+            .find(|frame| frame.starts_with("event: cancelled"))
+            .unwrap();
+        let data: Value = serde_json::from_str(cancelled.split_once("data: ").unwrap().1).unwrap();
+        assert_eq!(data["request_id"], request_id.as_str());
+        assert!(!rest.contains("event: done"));
+
+        for _ in 0..100 {
+            if dropped.load(Ordering::SeqCst) {
+                break;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(
+            dropped.load(Ordering::SeqCst),
+            "ollama stream was not cancelled"
+        );
+
+        ollama.stop(true).await;
+    }
 }
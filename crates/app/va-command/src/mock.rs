@@ -0,0 +1,92 @@
+use serde_json::{json, Value};
+
+use crate::backend::{
+    Backend, ChatFuture, ChatMessage, ChatRequest, FunctionCall, Role, Tool, ToolCall,
+};
+
+/// A deterministic stand-in for a model, for running va-command without one.
+///
+/// It calls the first tool whose name starts with a word of the command (`time_now` for "what time
+/// is it"), then answers with the tool's result; otherwise it echoes the command. The answer is
+/// streamed word by word. Generation options are ignored; with a `format`, the answer is wrapped
+/// into an intent named after the tool, or `none`.
+pub(crate) struct MockBackend;
+
+impl Backend for MockBackend {
+    fn chat<'a>(
+        &'a self,
+        request: ChatRequest<'a>,
+        on_content: &'a mut dyn FnMut(&str),
+    ) -> ChatFuture<'a> {
+        Box::pin(async move {
+            let mut reply = reply(request.messages, request.tools);
+            if request.format.is_some() && reply.tool_calls.is_empty() {
+                let intent = request
+                    .messages
+                    .last()
+                    .filter(|message| message.role == Role::Tool)
+                    .and_then(|message| message.tool_name.as_deref())
+                    .unwrap_or("none");
+                reply.content =
+                    json!({ "intent": intent, "slots": {}, "reply": reply.content }).to_string();
+            }
+            for (index, word) in reply.content.split(' ').enumerate() {
+                on_content(&if index == 0 {
+                    word.to_string()
+                } else {
+                    format!(" {word}")
+                });
+            }
+            Ok(reply)
+        })
+    }
+}
+
+fn reply(messages: &[ChatMessage], tools: &[Tool]) -> ChatMessage {
+    let results = messages
+        .iter()
+        .rev()
+        .take_while(|message| message.role == Role::Tool)
+        .collect::<Vec<_>>();
+    if !results.is_empty() {
+        let results = results
+            .iter()
+            .rev()
+            .map(|message| {
+                format!(
+                    "{} returned {}.",
+                    message.tool_name.as_deref().unwrap_or("tool"),
+                    message.content
+                )
+            })
+            .collect::<Vec<_>>();
+        return ChatMessage::new(Role::Assistant, results.join(" "));
+    }
+
+    let command = messages
+        .iter()
+        .rev()
+        .find(|message| message.role == Role::User)
+        .map(|message| message.content.as_str())
+        .unwrap_or_default();
+    let words = command.to_lowercase();
+    let tool = tools.iter().find(|tool| {
+        let prefix = tool.function.name.split('_').next().unwrap_or_default();
+        words
+            .split(|c: char| !c.is_alphanumeric())
+            .any(|word| word == prefix)
+    });
+    match tool {
+        Some(tool) => ChatMessage {
+            tool_calls: vec![ToolCall {
+                id: Some("mock_0".to_string()),
+                function: FunctionCall {
+                    name: tool.function.name.clone(),
+                    arguments: Value::Object(Default::default()),
+                },
+            }],
+            ..ChatMessage::new(Role::Assistant, "")
+        },
+        None => ChatMessage::new(Role::Assistant, format!("You said: {command}")),
+    }
+}
@@ -0,0 +1,128 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::backend::{
+    check_status, read_lines, Backend, BackendError, ChatFuture, ChatMessage, ChatRequest,
+    GenerationOptions, Role, Tool,
+};
+
+/// Ollama's `/api/chat`, streamed as NDJSON.
+pub(crate) struct OllamaBackend {
+    client: reqwest::Client,
+    url: String,
+    model: String,
+    keep_alive: Option<Value>,
+}
+
+#[derive(Serialize)]
+struct RequestBody<'a> {
+    model: &'a str,
+    messages: &'a [ChatMessage],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    tools: &'a [Tool],
+    /// Ollama's option names match [`GenerationOptions`].
+    #[serde(skip_serializing_if = "is_default")]
+    options: &'a GenerationOptions,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<&'a Value>,
+    /// Ollama constrains generation to this JSON Schema.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'a Value>,
+    stream: bool,
+}
+
+/// One line of the NDJSON reply stream. A failure after the stream started is reported as an
+/// `error` line.
+#[derive(Deserialize)]
+struct ChatChunk {
+    #[serde(default)]
+    message: Option<ChatMessage>,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+impl OllamaBackend {
+    pub(crate) fn new(
+        client: reqwest::Client,
+        base_url: &str,
+        model: &str,
+        keep_alive: Option<Value>,
+    ) -> Self {
+        Self {
+            client,
+            url: format!("{}/api/chat", base_url.trim_end_matches('/')),
+            model: model.to_string(),
+            keep_alive,
+        }
+    }
+
+    async fn send(
+        &self,
+        request: ChatRequest<'_>,
+        on_content: &mut dyn FnMut(&str),
+    ) -> Result<ChatMessage, BackendError> {
+        let request = RequestBody {
+            model: &self.model,
+            messages: request.messages,
+            tools: request.tools,
+            options: request.options,
+            keep_alive: request
+                .options
+                .keep_alive
+                .as_ref()
+                .or(self.keep_alive.as_ref()),
+            format: request.format,
+            stream: true,
+        };
+        let response = self.client.post(&self.url).json(&request).send().await?;
+        let response = check_status(response).await?;
+
+        let mut reply = ChatMessage::new(Role::Assistant, "");
+        read_lines(response, |line| apply_chunk(line, &mut reply, on_content)).await?;
+        Ok(reply)
+    }
+}
+
+impl Backend for OllamaBackend {
+    fn chat<'a>(
+        &'a self,
+        request: ChatRequest<'a>,
+        on_content: &'a mut dyn FnMut(&str),
+    ) -> ChatFuture<'a> {
+        Box::pin(self.send(request, on_content))
+    }
+}
+
+fn is_default(options: &&GenerationOptions) -> bool {
+    let options = GenerationOptions {
+        keep_alive: None,
+        ..(*options).clone()
+    };
+    options == GenerationOptions::default()
+}
+
+/// Adds one NDJSON line to the reply and returns whether it was the last one.
+fn apply_chunk(
+    line: &[u8],
+    reply: &mut ChatMessage,
+    on_content: &mut dyn FnMut(&str),
+) -> Result<bool, BackendError> {
+    if line.is_empty() {
+        return Ok(false);
+    }
+    let chunk: ChatChunk = serde_json::from_slice(line)
+        .map_err(|err| BackendError::InvalidResponse(err.to_string()))?;
+    if let Some(error) = chunk.error {
+        return Err(BackendError::Failed(error));
+    }
+    if let Some(message) = chunk.message {
+        if !message.content.is_empty() {
+            on_content(&message.content);
+            reply.content.push_str(&message.content);
+        }
+        reply.tool_calls.extend(message.tool_calls);
+    }
+    Ok(chunk.done)
+}
@@ -0,0 +1,496 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::backend::{
+    check_status, read_lines, Backend, BackendError, ChatFuture, ChatMessage, ChatRequest,
+    FunctionCall, Role, Tool, ToolCall,
+};
+
+/// An OpenAI-compatible `/chat/completions` endpoint (llama.cpp server, vLLM, LocalAI, ...),
+/// streamed as SSE.
+pub(crate) struct OpenAiBackend {
+    client: reqwest::Client,
+    url: String,
+    model: String,
+    api_key: Option<String>,
+}
+
+/// `num_ctx` has no counterpart here: the context size is fixed when the server starts.
+#[derive(Serialize)]
+struct RequestBody<'a> {
+    model: &'a str,
+    messages: Vec<Message<'a>>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    tools: &'a [Tool],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop: Option<&'a [String]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<ResponseFormat<'a>>,
+    stream: bool,
+}
+
+/// Structured output. Servers without `json_schema` support ignore it, so the reply still has to be
+/// checked.
+#[derive(Serialize)]
+struct ResponseFormat<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    json_schema: JsonSchema<'a>,
+}
+
+#[derive(Serialize)]
+struct JsonSchema<'a> {
+    name: &'static str,
+    schema: &'a Value,
+    /// Strict mode requires closed objects, but an intent's `slots` take any names.
+    strict: bool,
+}
+
+#[derive(Serialize)]
+struct Message<'a> {
+    role: Role,
+    content: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<MessageToolCall<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct MessageToolCall<'a> {
+    id: &'a str,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    function: MessageFunction<'a>,
+}
+
+/// OpenAI passes arguments as a JSON string.
+#[derive(Serialize)]
+struct MessageFunction<'a> {
+    name: &'a str,
+    arguments: String,
+}
+
+/// One `data:` line of the reply stream.
+#[derive(Deserialize)]
+struct ChatChunk {
+    #[serde(default)]
+    choices: Vec<Choice>,
+    #[serde(default)]
+    error: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    #[serde(default)]
+    delta: Delta,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct Delta {
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ToolCallDelta>,
+}
+
+/// A piece of a tool call. The name and the argument string arrive spread over several chunks with
+/// the same index.
+#[derive(Deserialize)]
+struct ToolCallDelta {
+    #[serde(default)]
+    index: usize,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    function: FunctionDelta,
+}
+
+#[derive(Default, Deserialize)]
+struct FunctionDelta {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    arguments: Option<String>,
+}
+
+#[derive(Default)]
+struct PartialCall {
+    id: Option<String>,
+    name: String,
+    arguments: String,
+}
+
+impl OpenAiBackend {
+    pub(crate) fn new(
+        client: reqwest::Client,
+        base_url: &str,
+        model: &str,
+        api_key: Option<String>,
+    ) -> Self {
+        Self {
+            client,
+            url: format!("{}/chat/completions", base_url.trim_end_matches('/')),
+            model: model.to_string(),
+            api_key,
+        }
+    }
+
+    async fn send(
+        &self,
+        request: ChatRequest<'_>,
+        on_content: &mut dyn FnMut(&str),
+    ) -> Result<ChatMessage, BackendError> {
+        let options = request.options;
+        let request = RequestBody {
+            model: &self.model,
+            messages: request.messages.iter().map(Message::from).collect(),
+            tools: request.tools,
+            temperature: options.temperature,
+            top_p: options.top_p,
+            max_tokens: options.num_predict,
+            seed: options.seed,
+            stop: options.stop.as_deref(),
+            response_format: request.format.map(|schema| ResponseFormat {
+                kind: "json_schema",
+                json_schema: JsonSchema {
+                    name: "reply",
+                    schema,
+                    strict: false,
+                },
+            }),
+            stream: true,
+        };
+        let mut builder = self.client.post(&self.url).json(&request);
+        if let Some(api_key) = &self.api_key {
+            builder = builder.bearer_auth(api_key);
+        }
+        let response = check_status(builder.send().await?).await?;
+
+        let mut content = String::new();
+        let mut calls = Vec::<PartialCall>::new();
+        read_lines(response, |line| {
+            let Some(data) = line.strip_prefix(b"data:") else {
+                // Blank separators, comments and `event:` lines.
+                return Ok(false);
+            };
+            let data = data.trim_ascii();
+            if data == b"[DONE]" {
+                return Ok(true);
+            }
+            let chunk: ChatChunk = serde_json::from_slice(data)
+                .map_err(|err| BackendError::InvalidResponse(err.to_string()))?;
+            if let Some(error) = chunk.error {
+                let message = error
+                    .get("message")
+                    .and_then(Value::as_str)
+                    .map(str::to_string);
+                return Err(BackendError::Failed(
+                    message.unwrap_or_else(|| error.to_string()),
+                ));
+            }
+
+            let mut finished = false;
+            for choice in chunk.choices {
+                if let Some(text) = choice.delta.content.filter(|text| !text.is_empty()) {
+                    on_content(&text);
+                    content.push_str(&text);
+                }
+                for delta in choice.delta.tool_calls {
+                    if calls.len() <= delta.index {
+                        calls.resize_with(delta.index + 1, PartialCall::default);
+                    }
+                    let call = &mut calls[delta.index];
+                    call.id = delta.id.or(call.id.take());
+                    call.name.push_str(&delta.function.name.unwrap_or_default());
+                    call.arguments
+                        .push_str(&delta.function.arguments.unwrap_or_default());
+                }
+                finished |= choice.finish_reason.is_some();
+            }
+            Ok(finished)
+        })
+        .await?;
+
+        Ok(ChatMessage {
+            tool_calls: calls.into_iter().enumerate().map(tool_call).collect(),
+            ..ChatMessage::new(Role::Assistant, content)
+        })
+    }
+}
+
+impl Backend for OpenAiBackend {
+    fn chat<'a>(
+        &'a self,
+        request: ChatRequest<'a>,
+        on_content: &'a mut dyn FnMut(&str),
+    ) -> ChatFuture<'a> {
+        Box::pin(self.send(request, on_content))
+    }
+}
+
+impl<'a> From<&'a ChatMessage> for Message<'a> {
+    fn from(message: &'a ChatMessage) -> Self {
+        Self {
+            role: message.role,
+            content: &message.content,
+            tool_calls: message
+                .tool_calls
+                .iter()
+                .map(|call| MessageToolCall {
+                    id: call.id.as_deref().unwrap_or_default(),
+                    kind: "function",
+                    function: MessageFunction {
+                        name: &call.function.name,
+                        arguments: call.function.arguments.to_string(),
+                    },
+                })
+                .collect(),
+            tool_call_id: message.tool_call_id.as_deref(),
+        }
+    }
+}
+
+/// Completes a streamed call. Arguments that are not valid JSON are passed on as a string, so the
+/// tool reports them to the model instead of the whole reply failing.
+fn tool_call((index, call): (usize, PartialCall)) -> ToolCall {
+    let arguments = if call.arguments.trim().is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_str(&call.arguments).unwrap_or(Value::String(call.arguments))
+    };
+    ToolCall {
+        id: Some(call.id.unwrap_or_else(|| format!("call_{index}"))),
+        function: FunctionCall {
+            name: call.name,
+            arguments,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::GenerationOptions;
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use serde_json::json;
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    type Received = Arc<Mutex<Vec<(Option<String>, Value)>>>;
+
+    /// Starts a server that answers every request with `frames` as an SSE stream and records the
+    /// `Authorization` header and body of each request.
+    async fn start_server(
+        frames: &'static [&'static str],
+    ) -> (String, Received, actix_web::dev::ServerHandle) {
+        let received = Received::default();
+        let received_clone = received.clone();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = HttpServer::new(move || {
+            let received = received_clone.clone();
+            App::new().route(
+                "/v1/chat/completions",
+                web::post().to(move |request: HttpRequest, payload: web::Json<Value>| {
+                    let received = received.clone();
+                    async move {
+                        let authorization = request
+                            .headers()
+                            .get("authorization")
+                            .map(|value| value.to_str().unwrap().to_string());
+                        received
+                            .lock()
+                            .unwrap()
+                            .push((authorization, payload.into_inner()));
+                        let body = frames
+                            .iter()
+                            .map(|frame| format!("data: {frame}\n\n"))
+                            .collect::<String>();
+                        HttpResponse::Ok()
+                            .content_type("text/event-stream")
+                            .body(body)
+                    }
+                }),
+            )
+        })
+        .shutdown_timeout(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        (format!("http://{addr}/v1"), received, handle)
+    }
+
+    #[actix_web::test]
+    async fn assembles_streamed_tool_calls() {
+        let (url, received, handle) = start_server(&[
+            concat!(
+                r#"{"choices":[{"delta":{"role":"assistant","#,
+                r#""tool_calls":[{"index":0,"id":"call_abc","#,
+                r#""type":"function","function":{"name":"time_now","arguments":""}}]}}]}"#,
+            ),
+            concat!(
+                r#"{"choices":[{"delta":{"tool_calls":[{"index":0,"#,
+                r#""function":{"arguments":"{\"zone\":"}}]}}]}"#,
+            ),
+            concat!(
+                r#"{"choices":[{"delta":{"tool_calls":[{"index":0,"#,
+                r#""function":{"arguments":"\"utc\"}"}}]},"finish_reason":"tool_calls"}]}"#,
+            ),
+            "[DONE]",
+        ])
+        .await;
+        let backend = OpenAiBackend::new(
+            reqwest::Client::new(),
+            &url,
+            "local",
+            Some("sk-test".to_string()),
+        );
+        let messages = [ChatMessage::new(Role::User, "what time is it")];
+        let tools = [Tool::function(
+            "time_now".to_string(),
+            "Time".to_string(),
+            json!({}),
+        )];
+
+        let options = GenerationOptions {
+            temperature: Some(0.2),
+            num_ctx: Some(4096),
+            num_predict: Some(64),
+            stop: Some(vec!["User:".to_string()]),
+            ..GenerationOptions::default()
+        };
+        let request = ChatRequest {
+            messages: &messages,
+            tools: &tools,
+            options: &options,
+            format: None,
+        };
+        let reply = backend.chat(request, &mut |_| {}).await.unwrap();
+
+        assert_eq!(reply.tool_calls.len(), 1);
+        assert_eq!(reply.tool_calls[0].id.as_deref(), Some("call_abc"));
+        assert_eq!(reply.tool_calls[0].function.name, "time_now");
+        assert_eq!(
+            reply.tool_calls[0].function.arguments,
+            json!({ "zone": "utc" })
+        );
+
+        let (authorization, request) = received.lock().unwrap()[0].clone();
+        assert_eq!(authorization.as_deref(), Some("Bearer sk-test"));
+        assert_eq!(request["model"], "local");
+        assert_eq!(request["stream"], true);
+        assert_eq!(request["tools"][0]["function"]["name"], "time_now");
+        assert_eq!(request["temperature"], 0.2f32);
+        assert_eq!(request["max_tokens"], 64);
+        assert_eq!(request["stop"], json!(["User:"]));
+        assert!(request.get("num_ctx").is_none());
+        assert!(request.get("response_format").is_none());
+
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn streams_content_and_sends_tool_results() {
+        let (url, received, handle) = start_server(&[
+            r#"{"choices":[{"delta":{"role":"assistant","content":"It is "}}]}"#,
+            r#"{"choices":[{"delta":{"content":"10:30."},"finish_reason":null}]}"#,
+            r#"{"choices":[{"delta":{},"finish_reason":"stop"}]}"#,
+        ])
+        .await;
+        let backend = OpenAiBackend::new(reqwest::Client::new(), &url, "local", None);
+        let call = ToolCall {
+            id: Some("call_abc".to_string()),
+            function: FunctionCall {
+                name: "time_now".to_string(),
+                arguments: json!({}),
+            },
+        };
+        let messages = [
+            ChatMessage::new(Role::User, "what time is it"),
+            ChatMessage {
+                tool_calls: vec![call.clone()],
+                ..ChatMessage::new(Role::Assistant, "")
+            },
+            ChatMessage::tool_result(&call, r#"{"time":"10:30"}"#.to_string()),
+        ];
+
+        let mut pieces = Vec::new();
+        let schema = json!({ "type": "object" });
+        let request = ChatRequest {
+            messages: &messages,
+            tools: &[],
+            options: &GenerationOptions::default(),
+            format: Some(&schema),
+        };
+        let reply = backend
+            .chat(request, &mut |text| pieces.push(text.to_string()))
+            .await
+            .unwrap();
+
+        assert_eq!(pieces, ["It is ", "10:30."]);
+        assert_eq!(reply.content, "It is 10:30.");
+        assert!(reply.tool_calls.is_empty());
+
+        let (authorization, request) = received.lock().unwrap()[0].clone();
+        assert_eq!(authorization, None);
+        assert!(request.get("tools").is_none());
+        assert!(request.get("temperature").is_none());
+        assert_eq!(
+            request["response_format"],
+            json!({
+                "type": "json_schema",
+                "json_schema": { "name": "reply", "schema": schema, "strict": false },
+            })
+        );
+        assert_eq!(
+            request["messages"][1]["tool_calls"][0],
+            json!({
+                "id": "call_abc",
+                "type": "function",
+                "function": { "name": "time_now", "arguments": "{}" },
+            })
+        );
+        assert_eq!(
+            request["messages"][2],
+            json!({ "role": "tool", "content": r#"{"time":"10:30"}"#, "tool_call_id": "call_abc" })
+        );
+
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn reports_stream_errors() {
+        let (url, _received, handle) =
+            start_server(&[r#"{"error":{"message":"context length exceeded"}}"#]).await;
+        let backend = OpenAiBackend::new(reqwest::Client::new(), &url, "local", None);
+
+        let request = ChatRequest {
+            messages: &[ChatMessage::new(Role::User, "hi")],
+            tools: &[],
+            options: &GenerationOptions::default(),
+            format: None,
+        };
+        let err = backend.chat(request, &mut |_| {}).await.unwrap_err();
+        assert!(
+            matches!(err, BackendError::Failed(message) if message == "context length exceeded")
+        );
+
+        handle.stop(true).await;
+    }
+}
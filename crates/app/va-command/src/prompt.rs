@@ -0,0 +1,160 @@
+use std::fs;
+use std::path::Path;
+
+use crate::error::Error;
+
+/// Used when `PROMPT_FILE` is not set.
+pub(crate) const DEFAULT_TEMPLATE: &str =
+    "You are a voice assistant. Answer the user's request in \
+one or two short sentences that read well aloud. Use the provided tools when the request needs \
+information they return.";
+
+/// Values a template can refer to as `{{name}}`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Variable {
+    Command,
+    Time,
+    Locale,
+    Tools,
+    History,
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Text(String),
+    Variable(Variable),
+}
+
+/// A system prompt with `{{variable}}` placeholders, checked when it is loaded so rendering cannot
+/// fail.
+#[derive(Clone, Debug)]
+pub(crate) struct Template {
+    segments: Vec<Segment>,
+}
+
+/// What the placeholders are replaced with.
+pub(crate) struct PromptValues<'a> {
+    pub(crate) command: &'a str,
+    pub(crate) time: &'a str,
+    pub(crate) locale: &'a str,
+    /// One `- name: description` line per tool.
+    pub(crate) tools: &'a str,
+    /// Earlier turns of the session as `User: ...` and `Assistant: ...` lines.
+    pub(crate) history: &'a str,
+}
+
+impl Variable {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "command" => Some(Self::Command),
+            "time" => Some(Self::Time),
+            "locale" => Some(Self::Locale),
+            "tools" => Some(Self::Tools),
+            "history" => Some(Self::History),
+            _ => None,
+        }
+    }
+}
+
+impl Template {
+    pub(crate) fn load(path: &Path) -> Result<Self, Error> {
+        let source = fs::read_to_string(path)
+            .map_err(|err| format!("failed to read prompt file {}: {err}", path.display()))?;
+        Self::parse(&source)
+            .map_err(|err| format!("invalid prompt file {}: {err}", path.display()).into())
+    }
+
+    pub(crate) fn parse(source: &str) -> Result<Self, Error> {
+        let source = source.trim();
+        let mut segments = Vec::new();
+        let mut rest = source;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_string()));
+            }
+            let offset = source.len() - rest.len() + start;
+            let line = source[..offset].matches('\n').count() + 1;
+            let after = &rest[start + 2..];
+            let end = after
+                .find("}}")
+                .ok_or_else(|| format!("line {line}: unclosed {{{{"))?;
+            let name = after[..end].trim();
+            let variable = Variable::parse(name)
+                .ok_or_else(|| format!("line {line}: unknown variable {{{{{name}}}}}"))?;
+            segments.push(Segment::Variable(variable));
+            rest = &after[end + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+        if segments.is_empty() {
+            return Err("prompt must not be empty".into());
+        }
+        Ok(Self { segments })
+    }
+
+    pub(crate) fn uses(&self, variable: Variable) -> bool {
+        self.segments
+            .iter()
+            .any(|segment| matches!(segment, Segment::Variable(used) if *used == variable))
+    }
+
+    pub(crate) fn render(&self, values: &PromptValues<'_>) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text.as_str(),
+                Segment::Variable(Variable::Command) => values.command,
+                Segment::Variable(Variable::Time) => values.time,
+                Segment::Variable(Variable::Locale) => values.locale,
+                Segment::Variable(Variable::Tools) => values.tools,
+                Segment::Variable(Variable::History) => values.history,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALUES: PromptValues<'static> = PromptValues {
+        command: "what time is it",
+        time: "Sunday, 2026-10-18 10:30",
+        locale: "de-DE",
+        tools: "- time_now: Return the server's current local time.",
+        history: "",
+    };
+
+    #[test]
+    fn renders_variables() {
+        let template =
+            Template::parse("Answer in {{locale}}. It is {{ time }}.\nTools:\n{{tools}}\n")
+                .unwrap();
+
+        assert_eq!(
+            template.render(&VALUES),
+            "Answer in de-DE. It is Sunday, 2026-10-18 10:30.\nTools:\n\
+             - time_now: Return the server's current local time."
+        );
+        assert!(template.uses(Variable::Tools));
+        assert!(!template.uses(Variable::History));
+    }
+
+    #[test]
+    fn default_template_is_valid() {
+        assert_eq!(
+            Template::parse(DEFAULT_TEMPLATE).unwrap().render(&VALUES),
+            DEFAULT_TEMPLATE
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        let err = Template::parse("Answer.\nIn {{language}}.").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: unknown variable {{language}}");
+        let err = Template::parse("Answer in {{locale").unwrap_err();
+        assert_eq!(err.to_string(), "line 1: unclosed {{");
+        assert!(Template::parse("  \n").is_err());
+    }
+}
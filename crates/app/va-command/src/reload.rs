@@ -0,0 +1,125 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use tracing::{info, warn};
+
+use crate::error::Error;
+use crate::prompt::Template;
+
+/// Holds the active prompt template and swaps it when the prompt file changes. A file that does not
+/// validate is reported and the previous template stays active.
+pub(crate) struct PromptStore {
+    path: Option<PathBuf>,
+    current: RwLock<Arc<Template>>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl PromptStore {
+    pub(crate) fn new(path: Option<PathBuf>, template: Template) -> Self {
+        let modified = path.as_ref().and_then(|path| modified_at(path));
+        Self {
+            path,
+            current: RwLock::new(Arc::new(template)),
+            modified: Mutex::new(modified),
+        }
+    }
+
+    pub(crate) fn current(&self) -> Arc<Template> {
+        self.current.read().unwrap().clone()
+    }
+
+    pub(crate) fn reload(&self) -> Result<(), Error> {
+        let Some(path) = &self.path else {
+            return Err("PROMPT_FILE is not set".into());
+        };
+
+        *self.modified.lock().unwrap() = modified_at(path);
+        let template = Template::load(path)?;
+        *self.current.write().unwrap() = Arc::new(template);
+        info!("prompt reloaded from {}", path.display());
+        Ok(())
+    }
+
+    /// Reloads when the file's modification time differs from the last load.
+    fn reload_if_changed(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        if modified_at(path) == *self.modified.lock().unwrap() {
+            return;
+        }
+        if let Err(err) = self.reload() {
+            warn!("keeping previous prompt: {err}");
+        }
+    }
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Polls the prompt file for changes every `interval`.
+pub(crate) async fn watch(store: Arc<PromptStore>, interval: Duration) {
+    let mut ticker = actix_web::rt::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        store.reload_if_changed();
+    }
+}
+
+/// Reloads the prompt file on `SIGHUP`.
+#[cfg(unix)]
+pub(crate) async fn reload_on_sighup(store: Arc<PromptStore>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(err) => {
+            warn!("failed to listen for SIGHUP: {err}");
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        info!("SIGHUP received");
+        if let Err(err) = store.reload() {
+            warn!("keeping previous prompt: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prompt::PromptValues;
+
+    const VALUES: PromptValues<'static> = PromptValues {
+        command: "",
+        time: "",
+        locale: "fr-FR",
+        tools: "",
+        history: "",
+    };
+
+    #[test]
+    fn reload_keeps_previous_template_when_invalid() {
+        let path =
+            std::env::temp_dir().join(format!("va-command-{}-prompt.txt", std::process::id()));
+        fs::write(&path, "Answer in {{locale}}.").unwrap();
+        let store = PromptStore::new(Some(path.clone()), Template::load(&path).unwrap());
+
+        fs::write(&path, "Réponds en {{locale}}.").unwrap();
+        store.reload().unwrap();
+        assert_eq!(store.current().render(&VALUES), "Réponds en fr-FR.");
+
+        fs::write(&path, "Réponds en {{langue}}.").unwrap();
+        assert!(store.reload().is_err());
+        assert_eq!(store.current().render(&VALUES), "Réponds en fr-FR.");
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::oneshot;
+
+type InFlightMap = Arc<Mutex<HashMap<String, InFlight>>>;
+
+/// Commands being answered, by request id, so they can be cancelled.
+#[derive(Default)]
+pub(crate) struct Requests {
+    inflight: InFlightMap,
+    next_number: AtomicU64,
+}
+
+struct InFlight {
+    source: Option<String>,
+    /// Tells the entry apart from a later request that reuses the id.
+    number: u64,
+    cancel: oneshot::Sender<()>,
+}
+
+/// A registered request. Dropping it unregisters the request.
+pub(crate) struct RequestGuard {
+    id: String,
+    number: u64,
+    inflight: InFlightMap,
+    cancelled: oneshot::Receiver<()>,
+}
+
+impl Requests {
+    /// Registers a request. A request still running under the same id, e.g. one a client retried
+    /// after a timeout, is cancelled.
+    pub(crate) fn start(&self, id: String, source: Option<&str>) -> RequestGuard {
+        let number = self.next_number.fetch_add(1, Ordering::Relaxed);
+        let (cancel, cancelled) = oneshot::channel();
+        let entry = InFlight {
+            source: source.map(str::to_string),
+            number,
+            cancel,
+        };
+        if let Some(previous) = self.inflight.lock().unwrap().insert(id.clone(), entry) {
+            let _ = previous.cancel.send(());
+        }
+        RequestGuard {
+            id,
+            number,
+            inflight: self.inflight.clone(),
+            cancelled,
+        }
+    }
+
+    /// Returns whether a request with the id was running.
+    pub(crate) fn cancel(&self, id: &str) -> bool {
+        match self.inflight.lock().unwrap().remove(id) {
+            Some(request) => {
+                let _ = request.cancel.send(());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Cancels every request from `source`, or every request without one, and returns how many
+    /// there were.
+    pub(crate) fn cancel_source(&self, source: Option<&str>) -> usize {
+        let mut inflight = self.inflight.lock().unwrap();
+        let ids = inflight
+            .iter()
+            .filter(|(_, request)| request.source.as_deref() == source)
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        for id in &ids {
+            if let Some(request) = inflight.remove(id) {
+                let _ = request.cancel.send(());
+            }
+        }
+        ids.len()
+    }
+}
+
+impl RequestGuard {
+    pub(crate) fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Resolves once the request is cancelled. Racing the work against it drops the work, which
+    /// aborts its requests to the model and to va-actions.
+    pub(crate) async fn cancelled(&mut self) {
+        // The sender is only dropped after sending, when the request is cancelled.
+        let _ = (&mut self.cancelled).await;
+    }
+}
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        let mut inflight = self.inflight.lock().unwrap();
+        if inflight
+            .get(&self.id)
+            .is_some_and(|request| request.number == self.number)
+        {
+            inflight.remove(&self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn cancels_by_id_and_source() {
+        let requests = Requests::default();
+        let mut first = requests.start("a".to_string(), Some("kitchen"));
+        let _second = requests.start("b".to_string(), Some("kitchen"));
+        let _third = requests.start("c".to_string(), None);
+
+        assert!(requests.cancel("a"));
+        first.cancelled().await;
+        assert!(!requests.cancel("a"));
+
+        assert_eq!(requests.cancel_source(Some("kitchen")), 1);
+        assert_eq!(requests.cancel_source(Some("kitchen")), 0);
+        assert_eq!(requests.cancel_source(None), 1);
+    }
+
+    #[actix_web::test]
+    async fn replaces_requests_with_the_same_id() {
+        let requests = Requests::default();
+        let mut retried = requests.start("a".to_string(), None);
+        let retry = requests.start("a".to_string(), None);
+        retried.cancelled().await;
+
+        // The replaced request must not unregister its successor.
+        drop(retried);
+        assert!(requests.cancel("a"));
+
+        drop(retry);
+        assert!(!requests.cancel("a"));
+    }
+}
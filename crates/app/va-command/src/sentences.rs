@@ -0,0 +1,121 @@
+/// Cuts streamed text into sentences, so each can be spoken as soon as it is complete.
+///
+/// A sentence ends at a newline or at `.`, `!`, `?` or `…` (plus closing quotes or brackets)
+/// followed by whitespace. Decimals such as `3.5` are not split, abbreviations such as `e.g.` are.
+#[derive(Default)]
+pub(crate) struct Sentences {
+    buffer: String,
+    /// Bytes at the start of `buffer` that are known not to end a sentence.
+    scanned: usize,
+}
+
+impl Sentences {
+    /// Adds a piece of text and returns the sentences it completed.
+    pub(crate) fn push(&mut self, text: &str) -> Vec<String> {
+        self.buffer.push_str(text);
+        let mut sentences = Vec::new();
+        loop {
+            match sentence_end(&self.buffer, self.scanned) {
+                Ok(end) => {
+                    let rest = self.buffer.split_off(end);
+                    let sentence = std::mem::replace(&mut self.buffer, rest);
+                    self.scanned = 0;
+                    let sentence = sentence.trim();
+                    if !sentence.is_empty() {
+                        sentences.push(sentence.to_string());
+                    }
+                }
+                Err(scanned) => {
+                    self.scanned = scanned;
+                    return sentences;
+                }
+            }
+        }
+    }
+
+    /// Returns the text after the last complete sentence, if any.
+    pub(crate) fn finish(&mut self) -> Option<String> {
+        let rest = std::mem::take(&mut self.buffer);
+        self.scanned = 0;
+        let rest = rest.trim();
+        (!rest.is_empty()).then(|| rest.to_string())
+    }
+}
+
+/// Byte offset just past the first sentence in `text`, scanning from byte `from`. While no sentence
+/// is known to be complete, returns the offset to resume from once more text arrives.
+fn sentence_end(text: &str, from: usize) -> Result<usize, usize> {
+    for (index, c) in text[from..].char_indices() {
+        let index = from + index;
+        if c == '\n' {
+            return Ok(index + 1);
+        }
+        if !matches!(c, '.' | '!' | '?' | '…') {
+            continue;
+        }
+        let end = index + c.len_utf8();
+        let rest = &text[end..];
+        let closing = rest.len() - rest.trim_start_matches(['"', '\'', ')', '”', '’']).len();
+        if rest[closing..].starts_with(char::is_whitespace) {
+            return Ok(end + closing);
+        }
+        if closing == rest.len() {
+            // Whether this ends the sentence depends on the text that follows.
+            return Err(index);
+        }
+    }
+    Err(text.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(pieces: &[&str]) -> Vec<String> {
+        let mut sentences = Sentences::default();
+        let mut result = pieces
+            .iter()
+            .flat_map(|piece| sentences.push(piece))
+            .collect::<Vec<_>>();
+        result.extend(sentences.finish());
+        result
+    }
+
+    #[test]
+    fn splits_at_sentence_boundaries() {
+        assert_eq!(
+            split(&["Hello there. It", " is 10:30! Is that", " late? Bye"]),
+            ["Hello there.", "It is 10:30!", "Is that late?", "Bye"]
+        );
+        assert_eq!(
+            split(&["First line\n\nSecond line"]),
+            ["First line", "Second line"]
+        );
+        assert_eq!(
+            split(&["He said \"hi.\" Then", " left..."]),
+            ["He said \"hi.\"", "Then left..."]
+        );
+    }
+
+    #[test]
+    fn waits_for_whitespace_after_terminator() {
+        let mut sentences = Sentences::default();
+        assert!(sentences.push("It costs 3.").is_empty());
+        assert!(sentences.push("5 euros.").is_empty());
+        assert_eq!(sentences.push(" Done"), ["It costs 3.5 euros."]);
+        assert_eq!(sentences.finish().as_deref(), Some("Done"));
+        assert_eq!(sentences.finish(), None);
+    }
+
+    #[test]
+    fn resumes_scanning_where_it_stopped() {
+        let mut sentences = Sentences::default();
+        assert!(sentences.push("He said").is_empty());
+        assert_eq!(sentences.scanned, 7);
+        assert!(sentences.push(" \"hi.").is_empty());
+        assert_eq!(sentences.scanned, 11);
+        assert!(sentences.push("\"").is_empty());
+        assert_eq!(sentences.push(" Then"), ["He said \"hi.\""]);
+        assert_eq!(sentences.scanned, 5);
+    }
+}
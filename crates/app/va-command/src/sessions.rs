@@ -0,0 +1,206 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::backend::{ChatMessage, Role};
+
+/// How much of a conversation is remembered.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct SessionLimits {
+    /// Most recent turns kept per session; zero disables conversation memory.
+    pub(crate) max_turns: usize,
+    /// Estimated tokens the remembered turns may take up in the prompt.
+    pub(crate) max_tokens: usize,
+    /// Sessions idle for longer are forgotten.
+    pub(crate) ttl: Duration,
+}
+
+/// One command and the answer it got.
+#[derive(Clone, Debug)]
+struct Turn {
+    command: String,
+    answer: String,
+}
+
+struct Session {
+    turns: VecDeque<Turn>,
+    used: Instant,
+}
+
+/// Conversation history per session id. The oldest turns are dropped once a session exceeds its
+/// limits.
+pub(crate) struct Sessions {
+    limits: SessionLimits,
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl Sessions {
+    pub(crate) fn new(limits: SessionLimits) -> Self {
+        Self {
+            limits,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the remembered turns as chat messages, oldest first.
+    pub(crate) fn history(&self, id: &str, now: Instant) -> Vec<ChatMessage> {
+        let mut sessions = self.sessions.lock().unwrap();
+        self.expire(&mut sessions, now);
+        sessions
+            .get(id)
+            .map(|session| {
+                session
+                    .turns
+                    .iter()
+                    .flat_map(|turn| {
+                        [
+                            ChatMessage::new(Role::User, turn.command.clone()),
+                            ChatMessage::new(Role::Assistant, turn.answer.clone()),
+                        ]
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn record(&self, id: &str, command: &str, answer: &str, now: Instant) {
+        if self.limits.max_turns == 0 {
+            return;
+        }
+        let mut sessions = self.sessions.lock().unwrap();
+        self.expire(&mut sessions, now);
+        let session = sessions.entry(id.to_string()).or_insert_with(|| Session {
+            turns: VecDeque::new(),
+            used: now,
+        });
+        session.used = now;
+        session.turns.push_back(Turn {
+            command: command.to_string(),
+            answer: answer.to_string(),
+        });
+
+        let mut tokens = session.turns.iter().map(Turn::tokens).sum::<usize>();
+        while session.turns.len() > self.limits.max_turns || tokens > self.limits.max_tokens {
+            let Some(turn) = session.turns.pop_front() else {
+                break;
+            };
+            tokens -= turn.tokens();
+        }
+    }
+
+    /// Forgets a session. Returns whether it existed.
+    pub(crate) fn reset(&self, id: &str, now: Instant) -> bool {
+        let mut sessions = self.sessions.lock().unwrap();
+        self.expire(&mut sessions, now);
+        sessions.remove(id).is_some()
+    }
+
+    fn expire(&self, sessions: &mut HashMap<String, Session>, now: Instant) {
+        sessions.retain(|_, session| now.duration_since(session.used) < self.limits.ttl);
+    }
+}
+
+impl Turn {
+    fn tokens(&self) -> usize {
+        estimate_tokens(&self.command) + estimate_tokens(&self.answer)
+    }
+}
+
+/// Rough token count for limiting prompt size: about four characters per token for English text.
+fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: SessionLimits = SessionLimits {
+        max_turns: 2,
+        max_tokens: 1000,
+        ttl: Duration::from_secs(60),
+    };
+
+    fn contents(messages: &[ChatMessage]) -> Vec<&str> {
+        messages
+            .iter()
+            .map(|message| message.content.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_most_recent_turns() {
+        let sessions = Sessions::new(LIMITS);
+        let now = Instant::now();
+
+        sessions.record("kitchen", "one", "1", now);
+        sessions.record("kitchen", "two", "2", now);
+        sessions.record("kitchen", "three", "3", now);
+        sessions.record("office", "four", "4", now);
+
+        let history = sessions.history("kitchen", now);
+        assert_eq!(contents(&history), ["two", "2", "three", "3"]);
+        assert_eq!(history[0].role, Role::User);
+        assert_eq!(history[1].role, Role::Assistant);
+        assert_eq!(contents(&sessions.history("office", now)), ["four", "4"]);
+        assert!(sessions.history("hall", now).is_empty());
+    }
+
+    #[test]
+    fn drops_oldest_turns_over_token_limit() {
+        let sessions = Sessions::new(SessionLimits {
+            max_tokens: 10,
+            ..LIMITS
+        });
+        let now = Instant::now();
+
+        // 7 + 1 estimated tokens, then 2 + 1.
+        sessions.record("kitchen", &"a".repeat(28), "ok", now);
+        sessions.record("kitchen", "short", "ok", now);
+        assert_eq!(contents(&sessions.history("kitchen", now)), ["short", "ok"]);
+
+        // A single turn over the limit is not remembered at all.
+        sessions.record("kitchen", &"long ".repeat(20), "ok", now);
+        assert!(sessions.history("kitchen", now).is_empty());
+    }
+
+    #[test]
+    fn expires_idle_sessions() {
+        let sessions = Sessions::new(LIMITS);
+        let now = Instant::now();
+
+        sessions.record("kitchen", "one", "1", now);
+        sessions.record("office", "two", "2", now);
+        sessions.record("office", "three", "3", now + Duration::from_secs(50));
+
+        let later = now + Duration::from_secs(70);
+        assert!(sessions.history("kitchen", later).is_empty());
+        assert_eq!(
+            contents(&sessions.history("office", later)),
+            ["two", "2", "three", "3"]
+        );
+    }
+
+    #[test]
+    fn resets_sessions() {
+        let sessions = Sessions::new(LIMITS);
+        let now = Instant::now();
+
+        sessions.record("kitchen", "one", "1", now);
+        assert!(sessions.reset("kitchen", now));
+        assert!(!sessions.reset("kitchen", now));
+        assert!(sessions.history("kitchen", now).is_empty());
+    }
+
+    #[test]
+    fn zero_turns_disables_memory() {
+        let sessions = Sessions::new(SessionLimits {
+            max_turns: 0,
+            ..LIMITS
+        });
+        let now = Instant::now();
+
+        sessions.record("kitchen", "one", "1", now);
+        assert!(sessions.history("kitchen", now).is_empty());
+    }
+}
@@ -0,0 +1,122 @@
+use std::convert::Infallible;
+
+use actix_web::web::{self, Bytes};
+use futures_util::Stream;
+use serde::Serialize;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tracing::{info, warn};
+
+use crate::assistant;
+use crate::backend::GenerationOptions;
+use crate::intent::OutputFormat;
+use crate::requests::RequestGuard;
+use crate::sentences::Sentences;
+use crate::{AppState, WebhookResponse};
+
+#[derive(Serialize)]
+struct SentenceEvent<'a> {
+    text: &'a str,
+}
+
+/// Answers the command, sending a `sentence` event per completed sentence and then `done` with the
+/// full answer, or `error`. Generation stops as soon as the client disconnects, or with a
+/// `cancelled` event when the request is cancelled.
+///
+/// An intent is only complete once it parses, so for [`OutputFormat::Intent`] the sentences of its
+/// reply are sent together, right before `done`.
+pub(crate) async fn generate(
+    state: web::Data<AppState>,
+    command: String,
+    session: Option<String>,
+    options: GenerationOptions,
+    format: OutputFormat,
+    mut inflight: RequestGuard,
+    sender: UnboundedSender<Bytes>,
+) {
+    let request_id = inflight.id().to_string();
+    let work = async {
+        let mut sentences = Sentences::default();
+        let send_sentence = |text: &str| {
+            let _ = sender.send(frame("sentence", &SentenceEvent { text }));
+        };
+        let result = assistant::answer(
+            &state,
+            &command,
+            session.as_deref(),
+            &options,
+            format,
+            &mut |text| {
+                if format == OutputFormat::Text {
+                    sentences
+                        .push(text)
+                        .iter()
+                        .for_each(|sentence| send_sentence(sentence));
+                }
+            },
+        )
+        .await;
+
+        match result {
+            Ok(answer) => {
+                if format == OutputFormat::Intent {
+                    sentences
+                        .push(&answer.text)
+                        .iter()
+                        .for_each(|sentence| send_sentence(sentence));
+                }
+                if let Some(sentence) = sentences.finish() {
+                    send_sentence(&sentence);
+                }
+                let response = WebhookResponse {
+                    results: answer.results,
+                    intent: answer.intent,
+                    path: Some(answer.path),
+                    request_id: Some(request_id.clone()),
+                    ..WebhookResponse::new("ok", answer.text)
+                };
+                let _ = sender.send(frame("done", &response));
+            }
+            Err(err) => {
+                warn!("failed to answer command: {err}");
+                let response = WebhookResponse {
+                    request_id: Some(request_id.clone()),
+                    ..WebhookResponse::new("error", err.to_string())
+                };
+                let _ = sender.send(frame("error", &response));
+            }
+        }
+    };
+
+    tokio::select! {
+        () = work => {}
+        () = sender.closed() => info!("client disconnected, generation cancelled"),
+        () = inflight.cancelled() => {
+            info!("request {request_id} cancelled");
+            let response = WebhookResponse {
+                request_id: Some(request_id.clone()),
+                ..WebhookResponse::new("cancelled", "Request cancelled")
+            };
+            let _ = sender.send(frame("cancelled", &response));
+        }
+    }
+}
+
+/// SSE body that ends once [`generate`] is done.
+pub(crate) fn body(
+    receiver: UnboundedReceiver<Bytes>,
+) -> impl Stream<Item = Result<Bytes, Infallible>> + 'static {
+    futures_util::stream::unfold(receiver, |mut receiver| async move {
+        let frame = receiver.recv().await?;
+        Some((Ok(frame), receiver))
+    })
+}
+
+fn frame(event: &str, data: &impl Serialize) -> Bytes {
+    match serde_json::to_string(data) {
+        Ok(json) => Bytes::from(format!("event: {event}\ndata: {json}\n\n")),
+        Err(err) => {
+            warn!("failed to encode {event} event: {err}");
+            Bytes::new()
+        }
+    }
+}
@@ -3,6 +3,29 @@ use serde::{Deserialize, Serialize};
 pub const COMMAND_TIME_NOW: &str = "time.now";
 pub const COMMAND_DATE_NOW: &str = "date.now";
 
+/// A command as listed by `GET /commands`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CommandDescriptor {
+    pub command: String,
+    pub description: String,
+    /// JSON Schema of the command's arguments.
+    #[serde(default = "no_parameters")]
+    pub parameters: serde_json::Value,
+    pub example_request: serde_json::Value,
+    pub example_response: serde_json::Value,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CommandsResponse {
+    pub instructions: String,
+    pub commands: Vec<CommandDescriptor>,
+}
+
+/// Schema for commands that take no arguments.
+pub fn no_parameters() -> serde_json::Value {
+    serde_json::json!({ "type": "object", "properties": {} })
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(tag = "command")]
 pub enum CommandRequest {